@@ -1,9 +1,13 @@
-use std::{borrow::Borrow, num::NonZeroU16, rc::Rc};
+use std::num::NonZeroU16;
 
-use content::{sculptors::DungeonSculptor, Sculptor};
+use content::{
+    arrival_tile, sculptors::DungeonSculptor, ContentRegistry, LevelSculptor, Sculptor,
+    BASE_DEFINITIONS,
+};
 use engine::{
-    pos_to_vec2, vec2_to_pos, Actor, ActorTemplate, Atlas, AxialInput2D, FrameBuilder, Grid,
-    InputHandler, Instance, Material, MaterialFlags, Position, Renderer, Tile, World,
+    pos_to_vec2, vec2_to_pos, Action, Actor, ActorId, Atlas, AxialInput2D, DijkstraMap, Feature,
+    FeatureKind, FeatureState, FrameBuilder, Grid, InputHandler, Instance, LightSource, Movement,
+    Observer, PathOptions, Position, Renderer, World,
 };
 use nalgebra_glm::{vec2_to_vec3, Vec2, Vec3};
 #[cfg(target_arch = "wasm32")]
@@ -15,46 +19,69 @@ use winit::{
     window::WindowBuilder,
 };
 
+// Tiles out of sight are drawn the way they are remembered, without any light on them
+const MEMORY_TINT: [u8; 3] = [25; 3];
+// Tiles are drawn darker than the actors standing on them
+const TILE_BRIGHTNESS: f32 = 0.3;
+
+fn light_tint(light: [f32; 3], brightness: f32) -> [u8; 3] {
+    light.map(|x| (x.min(1.) * brightness * 255.) as u8)
+}
+
 pub fn frame_from_world<'a>(
-    grid: &Grid,
+    world: &World,
     atlas: &'a Atlas,
     mut frame_builder: FrameBuilder<'a>,
-    fov_emitter: Position,
+    observer: Observer,
 ) -> FrameBuilder<'a> {
-    for (
-        pos,
-        Tile {
-            occupier, material, ..
-        },
-    ) in &grid.tiles
-    {
+    let Some(knowledge) = world.knowledge(observer) else {
+        return frame_builder;
+    };
+
+    for (pos, remembered) in knowledge.remembered_tiles() {
         if frame_builder.is_culled(pos_to_vec2(*pos)) {
             continue;
         }
 
-        let (resource_name, is_obscured) = match (
-            &material.obscured_resource_name,
-            grid.los_check(fov_emitter, *pos, Some(8.)),
-        ) {
-            (Some(name), is_obscured) => (name, !is_obscured),
-            (None, true) => (&material.resource_name, false),
-            (None, false) => continue,
+        // Whatever is in sight is drawn as it is now, the rest as it was last seen
+        let is_visible = knowledge.is_visible(*pos);
+        let light = world.light_map().light_at(*pos);
+        let (material, occupier, feature) = match world.grid.get_tile(*pos) {
+            Some(tile) if is_visible => (&tile.material, tile.occupier, tile.feature),
+            _ => (&remembered.material, None, remembered.feature),
         };
 
-        if !is_obscured {
-            grid.mark_visible(*pos);
-            for (neighbour, _) in grid.tile_neumann_neighbours(*pos) {
-                grid.mark_visible(neighbour);
-            }
-        }
+        // Features are as memorable as walls
+        if let Some(feature) = feature {
+            let feature_sprite_idx = atlas
+                .resolve_resource(&feature.resource_name())
+                .map_or(0, |x| x.0);
 
-        if !grid.is_visible(*pos) {
-            continue;
+            frame_builder.draw_sprite(
+                feature_sprite_idx,
+                Instance {
+                    size: 1.0,
+                    pos: pos_to_vec2(*pos),
+                    layer: 2,
+                    angle: 0.0,
+                    tint: if is_visible {
+                        light_tint(light, 1.)
+                    } else {
+                        MEMORY_TINT
+                    },
+                },
+            );
         }
 
-        if let Some(actor) = occupier {
+        let resource_name = match (&material.obscured_resource_name, is_visible) {
+            (_, true) => &material.resource_name,
+            (Some(name), false) => name,
+            (None, false) => continue,
+        };
+
+        if let Some(actor) = occupier.and_then(|x| world.actor(x)) {
             let actor_sprite_idx = atlas
-                .resolve_resource(actor.get_data().actor().template().resource_name())
+                .resolve_resource(actor.template().resource_name())
                 .map_or(0, |x| x.0);
 
             frame_builder.draw_sprite(
@@ -62,9 +89,9 @@ pub fn frame_from_world<'a>(
                 Instance {
                     size: 1.0,
                     pos: pos_to_vec2(*pos),
-                    layer: 2,
+                    layer: 3,
                     angle: 0.0,
-                    tint: [255; 3],
+                    tint: light_tint(light, 1.),
                 },
             );
         }
@@ -78,7 +105,11 @@ pub fn frame_from_world<'a>(
                 pos: pos_to_vec2(*pos),
                 layer: 1,
                 angle: 0.0,
-                tint: if is_obscured { [25; 3] } else { [75; 3] },
+                tint: if is_visible {
+                    light_tint(light, TILE_BRIGHTNESS)
+                } else {
+                    MEMORY_TINT
+                },
             },
         );
     }
@@ -86,6 +117,47 @@ pub fn frame_from_world<'a>(
     frame_builder
 }
 
+// Sneks crawl towards the player once they are close enough to smell them
+pub fn chase_player(world: &World, chase_map: &DijkstraMap, actor: ActorId) -> Action {
+    const SMELL_DISTANCE: i32 = 12;
+
+    let Some(position) = world.actor(actor).map(|x| x.position()) else {
+        return Action::wait(actor);
+    };
+
+    match (
+        chase_map.value(position),
+        chase_map.downhill(&world.grid, position),
+    ) {
+        (Some(distance), Some(step)) if distance <= SMELL_DISTANCE => {
+            Action::move_actor(actor, step)
+        }
+        _ => Action::wait(actor),
+    }
+}
+
+// Doors opening and closing change where sneks can crawl, so the map is laid again
+fn refresh_chase_map(grid: &Grid, chase_map: &mut DijkstraMap, revision: &mut u64) {
+    if grid.flags_revision() != *revision {
+        chase_map.rebuild(grid);
+        *revision = grid.flags_revision();
+    }
+}
+
+// Sneks live on every 97th free tile, but never where someone arrives or leaves
+fn snek_tiles(grid: &Grid, arrival: Position) -> Vec<Position> {
+    let mut tiles: Vec<_> = grid
+        .tiles()
+        .values()
+        .filter(|x| x.is_walkable() && x.position != arrival)
+        .filter(|x| x.feature.map(|x| x.kind) != Some(FeatureKind::Stairs))
+        .map(|x| x.position)
+        .collect();
+    // Sorted, so the same seed places them on the same tiles
+    tiles.sort_by_key(|x| (x.y, x.x));
+    tiles.into_iter().step_by(97).collect()
+}
+
 // Palette:
 // https://lospec.com/palette-list/2bit-demichrome
 
@@ -153,28 +225,6 @@ pub fn run() -> anyhow::Result<()> {
             .expect("Couldn't append canvas to document body.");
     }
 
-    let snek = ActorTemplate::new("Snek", "creature.snek");
-    let snek = Rc::new(snek);
-
-    let player = ActorTemplate::new("Player", "creature.player");
-    let player = Rc::new(player);
-
-    let floor = Material::new(
-        "Basic Floor",
-        "tile.floor",
-        None::<String>,
-        MaterialFlags::PASSTHROUGH,
-    );
-
-    let wall = Material::new("Wall", "tile.wall", Some("tile.wall"), MaterialFlags::SOLID);
-
-    let mut sculptor = DungeonSculptor::new(
-        NonZeroU16::new(50).unwrap(),
-        ([4, 4], [10, 10]),
-        floor.clone(),
-        wall.clone(),
-    );
-
     let mut renderer = pollster::block_on(Renderer::new(window));
 
     let atlas = Atlas::default_from_device(
@@ -183,19 +233,90 @@ pub fn run() -> anyhow::Result<()> {
         &renderer.atlas_bind_layout,
     );
 
+    let content = ContentRegistry::from_ron(BASE_DEFINITIONS)?;
+    content.validate(&atlas)?;
+
+    let snek = content.template("snek")?.clone();
+    let player = content.template("player")?.clone();
+    let floor = content.material("floor")?.clone();
+    let wall = content.material("wall")?.clone();
+
+    // Set EVSTER_SEED to replay a world someone else got
+    let seed = std::env::var("EVSTER_SEED")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or_else(rand::random::<u64>);
+    log::info!("World seed: {seed}");
+
+    let dungeon = |seed| {
+        DungeonSculptor::new(
+            NonZeroU16::new(50).unwrap(),
+            ([4, 4], [10, 10]),
+            floor.clone(),
+            wall.clone(),
+            seed,
+        )
+        .with_doors(Feature::door(FeatureState::Closed))
+    };
+    let mut sculptor = dungeon(seed);
+
     let mut world = World::new(64, 64);
     sculptor.sculpt_all(&mut world.grid);
-    let start_tile = world
+    // Picked the same way as on the levels below, so the seed alone decides it
+    let start_tile = arrival_tile(&world.grid).unwrap();
+
+    let player = world
+        .spawn_player(start_tile, Actor::from_template(player))
+        .unwrap();
+    world
+        .insert_component(player, LightSource::new(7., [255, 220, 170]))
+        .unwrap();
+    world.set_ambient_light([0.; 3]);
+    world.track_knowledge(Observer::Actor(player));
+
+    // The stairs down are as far from the start as it gets, everything below is made up on the way
+    let descent = world
         .grid
-        .tiles
+        .tiles()
         .values()
-        .find(|x| x.is_walkable())
+        .filter(|x| x.is_walkable())
         .map(|x| x.position)
+        .max_by_key(|x| (x.y, x.x))
         .unwrap();
-
-    let player = world
+    world
         .grid
-        .put_actor(start_tile, Actor::from_template(player));
+        .set_feature(descent, Some(Feature::stairs()))
+        .unwrap();
+
+    for position in snek_tiles(&world.grid, start_tile) {
+        world.spawn_actor(position, Actor::from_template(snek.clone()));
+    }
+
+    world.set_level_generator(
+        LevelSculptor::new(dungeon(seed.wrapping_add(1)), 64, 64).with_population(
+            move |_, grid| {
+                let Some(arrival) = arrival_tile(grid) else {
+                    return vec![];
+                };
+
+                snek_tiles(grid, arrival)
+                    .into_iter()
+                    .map(|x| (x, Actor::from_template(snek.clone())))
+                    .collect()
+            },
+        ),
+    );
+
+    let mut chase_map = DijkstraMap::new(
+        &world.grid,
+        [(start_tile, 0)],
+        PathOptions::new(Movement::Moore),
+    );
+    let mut chase_revision = world.grid.flags_revision();
+    world.advance(&mut |world: &World, actor| {
+        refresh_chase_map(&world.grid, &mut chase_map, &mut chase_revision);
+        chase_player(world, &chase_map, actor)
+    });
 
     renderer.camera.borrow_mut().position = vec2_to_vec3(&pos_to_vec2(start_tile));
 
@@ -243,27 +364,45 @@ pub fn run() -> anyhow::Result<()> {
                         player_desired_move -= Position::new(1, 0);
                     }
 
-                    if player_desired_move != Position::zeros() {
-                        let player = player.as_ref().unwrap();
-                        world.submit_action(engine::Action::MoveActor {
-                            actor_ref: player.clone(),
-                            to: player.get_data().try_valid_data().unwrap().cached_position
-                                + player_desired_move,
-                        });
-                    }
-
-                    if camera_locked {
-                        let player_pos = player
-                            .as_ref()
-                            .borrow()
-                            .unwrap()
-                            .get_data()
-                            .try_valid_data()
-                            .unwrap()
-                            .cached_position;
-
-                        renderer.camera.borrow_mut().position =
-                            vec2_to_vec3(&pos_to_vec2(player_pos));
+                    let player_pos = world.actor(player).map(|x| x.position());
+                    let player_action = match player_pos {
+                        Some(pos) if player_desired_move != Position::zeros() => {
+                            Some(Action::move_actor(player, pos + player_desired_move))
+                        }
+                        Some(pos) if input_handler.is_pressed(Return) => {
+                            Some(Action::interact(player, pos))
+                        }
+                        _ => None,
+                    };
+
+                    if let (Some(player_pos), Some(action)) = (player_pos, player_action) {
+                        let level = world.level();
+                        match world.submit_action(action) {
+                            Ok(_) => {
+                                if let Some(new_pos) = world.actor(player).map(|x| x.position()) {
+                                    if world.level() == level {
+                                        chase_map.move_goal(&world.grid, player_pos, new_pos);
+                                    } else {
+                                        chase_map = DijkstraMap::new(
+                                            &world.grid,
+                                            [(new_pos, 0)],
+                                            PathOptions::new(Movement::Moore),
+                                        );
+                                        chase_revision = world.grid.flags_revision();
+                                    }
+                                }
+
+                                world.advance(&mut |world: &World, actor| {
+                                    refresh_chase_map(
+                                        &world.grid,
+                                        &mut chase_map,
+                                        &mut chase_revision,
+                                    );
+                                    chase_player(world, &chase_map, actor)
+                                });
+                            }
+                            Err(err) => log::info!("Player couldn't act: {err}"),
+                        }
                     }
 
                     if input_handler.is_pressed(Slash) {
@@ -299,6 +438,18 @@ pub fn run() -> anyhow::Result<()> {
         Event::RedrawRequested(window_id) if window_id == renderer.window().id() => {
             input_handler.flush();
 
+            for event in world.drain_events() {
+                match event {
+                    engine::Event::ActorMoved { actor, to, .. }
+                    | engine::Event::ActorTravelled { actor, at: to, .. }
+                        if camera_locked && actor == player =>
+                    {
+                        renderer.camera.borrow_mut().position = vec2_to_vec3(&pos_to_vec2(to));
+                    }
+                    event => log::trace!("{event:?}"),
+                }
+            }
+
             let cursor_pos = renderer.window_space_to_world(&cursor_pos);
             renderer.camera.borrow_mut().position += renderer.delta_time
                 * camera_speed
@@ -313,16 +464,7 @@ pub fn run() -> anyhow::Result<()> {
                 ui.label(format!("World Cursor Position: ({cursor_x}, {cursor_y})"));
             });
 
-            let player_pos = player
-                .as_ref()
-                .borrow()
-                .unwrap()
-                .get_data()
-                .try_valid_data()
-                .unwrap()
-                .cached_position;
-
-            let frame = frame_from_world(&world.grid, &atlas, frame_builder, player_pos);
+            let frame = frame_from_world(&world, &atlas, frame_builder, Observer::Actor(player));
 
             {
                 puffin::profile_scope!("End Frame & Present");
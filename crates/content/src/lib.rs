@@ -1,5 +1,7 @@
 #![feature(array_windows)]
 
+mod registry;
 mod worldgen;
 
+pub use registry::*;
 pub use worldgen::*;
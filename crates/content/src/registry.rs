@@ -0,0 +1,181 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use engine::{
+    ActorTemplate, Atlas, Catalog, Faction, Feature, Material, MaterialFlags, MaterialHandle,
+    MaterialId, TemplateId, DEFAULT_MOVEMENT_COST,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+// Materials and actors the game ships with
+pub const BASE_DEFINITIONS: &str = include_str!("assets/definitions.ron");
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ContentError {
+    #[error("malformed definitions: {0}")]
+    Malformed(#[from] ron::error::SpannedError),
+    #[error("material \"{material}\" has an unknown flag \"{flag}\"")]
+    UnknownFlag { material: MaterialId, flag: String },
+    #[error("\"{id}\" uses the sprite \"{resource}\", which isn't in the atlas")]
+    MissingSprite { id: String, resource: String },
+    #[error("there is no material \"{0}\"")]
+    UnknownMaterial(MaterialId),
+    #[error("there is no actor template \"{0}\"")]
+    UnknownTemplate(TemplateId),
+    #[error("actor \"{template}\" has a speed of {speed}, so it would never get a turn")]
+    InvalidSpeed { template: TemplateId, speed: i32 },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definitions {
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDefinition>,
+    #[serde(default)]
+    actors: BTreeMap<String, TemplateDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDefinition {
+    name: String,
+    resource: String,
+    #[serde(default)]
+    obscured_resource: Option<String>,
+    // Names of `MaterialFlags`, e.g. `FLOOR` or `SWIMMABLE`
+    flags: Vec<String>,
+    #[serde(default = "default_movement_cost")]
+    movement_cost: u32,
+}
+
+fn default_movement_cost() -> u32 {
+    DEFAULT_MOVEMENT_COST
+}
+
+// Anything left out keeps the default of `ActorTemplate`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateDefinition {
+    name: String,
+    resource: String,
+    #[serde(default)]
+    speed: Option<i32>,
+    #[serde(default)]
+    health: Option<i32>,
+    #[serde(default)]
+    damage: Option<i32>,
+    #[serde(default)]
+    faction: Option<u16>,
+    #[serde(default)]
+    sight_radius: Option<f32>,
+}
+
+// Materials and actor templates loaded from text, by their stable ids
+#[derive(Debug, Default, Clone)]
+pub struct ContentRegistry {
+    catalog: Catalog,
+}
+
+impl ContentRegistry {
+    pub fn from_ron(source: &str) -> Result<Self, ContentError> {
+        let definitions: Definitions = ron::from_str(source)?;
+        let mut catalog = Catalog::default();
+
+        for (id, definition) in definitions.materials {
+            let id = MaterialId(id);
+
+            let mut flags = MaterialFlags::empty();
+            for flag in definition.flags {
+                match MaterialFlags::from_name(&flag) {
+                    Some(x) => flags |= x,
+                    None => return Err(ContentError::UnknownFlag { material: id, flag }),
+                }
+            }
+
+            let material = Material::new(
+                definition.name,
+                definition.resource,
+                definition.obscured_resource,
+                flags,
+            )
+            .with_movement_cost(definition.movement_cost);
+            catalog.insert_material(id, material.into());
+        }
+
+        for (id, definition) in definitions.actors {
+            let id = TemplateId(id);
+
+            let mut template = ActorTemplate::new(definition.name, definition.resource);
+            if let Some(speed) = definition.speed {
+                if speed <= 0 {
+                    return Err(ContentError::InvalidSpeed {
+                        template: id,
+                        speed,
+                    });
+                }
+                template = template.with_speed(speed);
+            }
+            if let Some(health) = definition.health {
+                template = template.with_health(health);
+            }
+            if let Some(damage) = definition.damage {
+                template = template.with_damage(damage);
+            }
+            if let Some(faction) = definition.faction {
+                template = template.with_faction(Faction(faction));
+            }
+            if let Some(sight_radius) = definition.sight_radius {
+                template = template.with_sight_radius(sight_radius);
+            }
+            catalog.insert_template(id, Rc::new(template));
+        }
+
+        Ok(Self { catalog })
+    }
+
+    // Makes sure every sprite the definitions and features refer to can be drawn
+    pub fn validate(&self, atlas: &Atlas) -> Result<(), ContentError> {
+        let materials = self.catalog.materials().flat_map(|(id, material)| {
+            [
+                Some(&material.resource_name),
+                material.obscured_resource_name.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|resource| (id.0.clone(), resource.clone()))
+        });
+        let templates = self
+            .catalog
+            .templates()
+            .map(|(id, template)| (id.0.clone(), template.resource_name().to_string()));
+        let features = Feature::all().map(|x| (x.kind.name().to_string(), x.resource_name()));
+
+        for (id, resource) in materials.chain(templates).chain(features) {
+            if atlas.resolve_resource(&resource).is_none() {
+                return Err(ContentError::MissingSprite { id, resource });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn material(&self, id: &str) -> Result<&MaterialHandle, ContentError> {
+        let id = MaterialId(id.to_string());
+        self.catalog
+            .material(&id)
+            .ok_or(ContentError::UnknownMaterial(id))
+    }
+
+    pub fn template(&self, id: &str) -> Result<&Rc<ActorTemplate>, ContentError> {
+        let id = TemplateId(id.to_string());
+        self.catalog
+            .template(&id)
+            .ok_or(ContentError::UnknownTemplate(id))
+    }
+
+    // What saves refer to the definitions by
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+}
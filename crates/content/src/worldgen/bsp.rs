@@ -0,0 +1,207 @@
+use std::ops::Range;
+
+use engine::{AsPosition, Grid, MaterialHandle, Position, Rectangle};
+use rand::{Rng, SeedableRng};
+
+use crate::{build_walls, Layout, Sculptor, SculptorRng};
+
+pub const DEFAULT_SPLIT_RATIO: (f32, f32) = (0.35, 0.65);
+pub const DEFAULT_MIN_ROOM_SIZE: [i32; 2] = [3, 3];
+
+// Rooms spread evenly by splitting the region in two over and over,
+// unlike `DungeonSculptor` it never has to retry anything
+#[non_exhaustive]
+pub struct BspSculptor {
+    floor: MaterialHandle,
+    wall: MaterialHandle,
+
+    // Nothing gets split into parts smaller than this
+    min_leaf_size: Position,
+    min_room_size: Position,
+    // Where along a side a split can happen, as a share of its length
+    split_ratio: (f32, f32),
+
+    rng: SculptorRng,
+}
+
+impl BspSculptor {
+    pub fn new(
+        min_leaf_size: impl AsPosition,
+        floor: MaterialHandle,
+        wall: MaterialHandle,
+        seed: u64,
+    ) -> Self {
+        let min_room_size: Position = DEFAULT_MIN_ROOM_SIZE.into();
+        Self {
+            floor,
+            wall,
+            // Leaves have to fit a room and the walls around it
+            min_leaf_size: min_leaf_size.into().sup(&min_room_size.add_scalar(2)),
+            min_room_size,
+            split_ratio: DEFAULT_SPLIT_RATIO,
+            rng: SculptorRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn with_min_room_size(mut self, min_room_size: impl AsPosition) -> Self {
+        self.min_room_size = min_room_size.into().sup(&Position::new(1, 1));
+        self.min_leaf_size = self.min_leaf_size.sup(&self.min_room_size.add_scalar(2));
+        self
+    }
+
+    pub fn with_split_ratio(mut self, min: f32, max: f32) -> Self {
+        let (min, max) = (min.clamp(0., 1.), max.clamp(0., 1.));
+        self.split_ratio = (min.min(max), min.max(max));
+        self
+    }
+
+    // Where the area can be cut along the axis so both parts are big enough
+    fn split_at(&mut self, area: &Rectangle, axis: usize) -> Option<i32> {
+        let (start, end) = (area.min()[axis], area.max()[axis]);
+        let min_leaf = self.min_leaf_size[axis];
+        let (lowest, highest) = (start + min_leaf, end - min_leaf);
+        if lowest > highest {
+            return None;
+        }
+
+        let (min_ratio, max_ratio) = self.split_ratio;
+        let ratio = if min_ratio < max_ratio {
+            self.rng.gen_range(min_ratio..=max_ratio)
+        } else {
+            min_ratio
+        };
+        let at = start + ((end - start) as f32 * ratio).round() as i32;
+        Some(at.clamp(lowest, highest))
+    }
+
+    // Splits the area until it can't be split anymore and puts a room in every leaf.
+    // Returns the range of `rooms` that ended up in the area.
+    fn split(
+        &mut self,
+        area: Rectangle,
+        rooms: &mut Vec<Rectangle>,
+        corridors: &mut Vec<(Position, Position)>,
+    ) -> Range<usize> {
+        let size = area.max() - area.min();
+
+        // Long areas get cut across, square-ish ones either way
+        let axes = if size.x as f32 > size.y as f32 * 1.25 {
+            [0, 1]
+        } else if size.y as f32 > size.x as f32 * 1.25 {
+            [1, 0]
+        } else if self.rng.gen_bool(0.5) {
+            [0, 1]
+        } else {
+            [1, 0]
+        };
+
+        let split = axes
+            .into_iter()
+            .find_map(|axis| Some((axis, self.split_at(&area, axis)?)));
+
+        let Some((axis, at)) = split else {
+            let start = rooms.len();
+            rooms.push(self.room_in(&area));
+            return start..rooms.len();
+        };
+
+        let (mut first_max, mut second_min) = (area.max(), area.min());
+        first_max[axis] = at;
+        second_min[axis] = at;
+
+        let first = self.split(Rectangle::new(area.min(), first_max), rooms, corridors);
+        let second = self.split(Rectangle::new(second_min, area.max()), rooms, corridors);
+
+        // Siblings are joined through one room from each side
+        let a = rooms[self.rng.gen_range(first.clone())].centroid();
+        let b = rooms[self.rng.gen_range(second.clone())].centroid();
+        let bend: Position = if self.rng.gen_bool(0.5) {
+            [a.x, b.y]
+        } else {
+            [b.x, a.y]
+        }
+        .into();
+        corridors.push((a, bend));
+        corridors.push((bend, b));
+
+        first.start..second.end
+    }
+
+    fn room_in(&mut self, leaf: &Rectangle) -> Rectangle {
+        // One tile is left on every side for the walls
+        let (inner_min, inner_max) = (leaf.min().add_scalar(1), leaf.max().add_scalar(-1));
+        let space = inner_max - inner_min;
+
+        let width = self
+            .rng
+            .gen_range(self.min_room_size.x.min(space.x)..=space.x);
+        let height = self
+            .rng
+            .gen_range(self.min_room_size.y.min(space.y)..=space.y);
+        let x = self.rng.gen_range(inner_min.x..=inner_max.x - width);
+        let y = self.rng.gen_range(inner_min.y..=inner_max.y - height);
+
+        Rectangle::new([x, y], [x + width, y + height])
+    }
+}
+
+impl Sculptor for BspSculptor {
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        self.sculpt_into(from, to, grid, &mut Layout::default());
+    }
+
+    #[profiling::function]
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        layout: &mut Layout,
+    ) {
+        let area = Rectangle::new(from, to);
+        let size = area.max() - area.min();
+        if size.x < self.min_leaf_size.x || size.y < self.min_leaf_size.y {
+            return;
+        }
+
+        let mut rooms = vec![];
+        let mut corridors = vec![];
+        {
+            profiling::scope!("Splitting");
+            self.split(area, &mut rooms, &mut corridors);
+        }
+
+        for (from, to) in &corridors {
+            grid.make_tile_box(from + Position::new(1, 1), *to, self.floor.clone());
+            grid.make_tile_at(*from, self.floor.clone());
+        }
+
+        for room in &rooms {
+            grid.make_tile_box(room.min(), room.max(), self.floor.clone());
+        }
+
+        build_walls(grid, &self.floor, &self.wall);
+
+        layout.rooms.extend(rooms);
+        layout.corridors.extend(corridors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{floor, tiles, wall};
+
+    fn bsp(seed: u64) -> Grid {
+        let mut grid = Grid::new(40, 30);
+        BspSculptor::new([8, 8], floor(), wall(), seed).sculpt_all(&mut grid);
+        grid
+    }
+
+    #[test]
+    fn same_seed_sculpts_same_grid() {
+        for seed in 0..4 {
+            assert_eq!(tiles(&bsp(seed)), tiles(&bsp(seed)));
+        }
+    }
+}
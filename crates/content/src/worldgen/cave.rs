@@ -0,0 +1,220 @@
+use engine::{AsPosition, Grid, MaterialHandle, Position};
+use rand::{Rng, SeedableRng};
+
+use crate::{build_walls, Sculptor, SculptorRng};
+
+// Share of the region that starts out as rock
+pub const DEFAULT_FILL_CHANCE: f64 = 0.45;
+pub const DEFAULT_SMOOTHING_STEPS: u32 = 4;
+// Rock grows on open ground with at least 5 rocky neighbours
+// and stays where it has at least 4, out of 8 Moore neighbours
+pub const DEFAULT_BIRTH_LIMIT: u8 = 5;
+pub const DEFAULT_SURVIVAL_LIMIT: u8 = 4;
+
+// Organic caves grown from random noise by a cellular automaton
+#[non_exhaustive]
+pub struct CaveSculptor {
+    floor: MaterialHandle,
+    wall: MaterialHandle,
+
+    fill_chance: f64,
+    smoothing_steps: u32,
+    birth_limit: u8,
+    survival_limit: u8,
+
+    rng: SculptorRng,
+}
+
+impl CaveSculptor {
+    pub fn new(floor: MaterialHandle, wall: MaterialHandle, seed: u64) -> Self {
+        Self {
+            floor,
+            wall,
+            fill_chance: DEFAULT_FILL_CHANCE,
+            smoothing_steps: DEFAULT_SMOOTHING_STEPS,
+            birth_limit: DEFAULT_BIRTH_LIMIT,
+            survival_limit: DEFAULT_SURVIVAL_LIMIT,
+            rng: SculptorRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn with_fill_chance(mut self, fill_chance: f64) -> Self {
+        self.fill_chance = fill_chance.clamp(0., 1.);
+        self
+    }
+
+    pub fn with_smoothing_steps(mut self, smoothing_steps: u32) -> Self {
+        self.smoothing_steps = smoothing_steps;
+        self
+    }
+
+    // How many rocky neighbours it takes for rock to appear and to stay
+    pub fn with_rules(mut self, birth_limit: u8, survival_limit: u8) -> Self {
+        self.birth_limit = birth_limit;
+        self.survival_limit = survival_limit;
+        self
+    }
+}
+
+// Rock of the region being sculpted, row by row
+struct Cells {
+    width: i32,
+    height: i32,
+    rock: Vec<bool>,
+}
+
+impl Cells {
+    // Everything outside the region counts as rock, so caves never spill out of it
+    fn is_rock(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return true;
+        }
+        self.rock[(y * self.width + x) as usize]
+    }
+
+    fn rocky_neighbours(&self, x: i32, y: i32) -> u8 {
+        let mut count = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) != (0, 0) && self.is_rock(x + dx, y + dy) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn smoothed(&self, birth_limit: u8, survival_limit: u8) -> Vec<bool> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let neighbours = self.rocky_neighbours(x, y);
+                if self.is_rock(x, y) {
+                    neighbours >= survival_limit
+                } else {
+                    neighbours >= birth_limit
+                }
+            })
+            .collect()
+    }
+
+    // Fills in every open pocket except the largest one, so the whole cave can be walked
+    fn fill_pockets(&mut self) {
+        let mut pocket_of = vec![None; self.rock.len()];
+        let mut pocket_sizes = vec![];
+
+        for start in 0..self.rock.len() {
+            if self.rock[start] || pocket_of[start].is_some() {
+                continue;
+            }
+
+            let pocket = pocket_sizes.len();
+            let mut size = 0;
+            let mut stack = vec![start];
+            pocket_of[start] = Some(pocket);
+
+            while let Some(index) = stack.pop() {
+                size += 1;
+                let (x, y) = (index as i32 % self.width, index as i32 / self.width);
+
+                // Only orthogonal steps, a diagonal squeeze between two rocks doesn't count
+                for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if self.is_rock(nx, ny) {
+                        continue;
+                    }
+
+                    let neighbour = (ny * self.width + nx) as usize;
+                    if pocket_of[neighbour].is_none() {
+                        pocket_of[neighbour] = Some(pocket);
+                        stack.push(neighbour);
+                    }
+                }
+            }
+
+            pocket_sizes.push(size);
+        }
+
+        // The first of the largest ones, so the result doesn't depend on anything but the cells
+        let Some(largest) =
+            (0..pocket_sizes.len()).max_by_key(|x| (pocket_sizes[*x], usize::MAX - x))
+        else {
+            return;
+        };
+
+        for (rock, pocket) in self.rock.iter_mut().zip(pocket_of) {
+            if pocket != Some(largest) {
+                *rock = true;
+            }
+        }
+    }
+}
+
+impl Sculptor for CaveSculptor {
+    #[profiling::function]
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        let (from, to): (Position, Position) = (from.into(), to.into());
+        let (width, height) = (to.x - from.x, to.y - from.y);
+        if width <= 0 || height <= 0 {
+            return;
+        }
+
+        let mut cells = Cells {
+            width,
+            height,
+            rock: (0..width * height)
+                .map(|_| self.rng.gen_bool(self.fill_chance))
+                .collect(),
+        };
+
+        {
+            profiling::scope!("Smoothing");
+            for _ in 0..self.smoothing_steps {
+                cells.rock = cells.smoothed(self.birth_limit, self.survival_limit);
+            }
+        }
+
+        // The outermost ring stays rock, so the walls fit inside the region
+        for y in 0..height {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    cells.rock[(y * width + x) as usize] = true;
+                }
+            }
+        }
+
+        {
+            profiling::scope!("Filling Pockets");
+            cells.fill_pockets();
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                if !cells.is_rock(x, y) {
+                    grid.make_tile_at(from + Position::new(x, y), self.floor.clone());
+                }
+            }
+        }
+
+        build_walls(grid, &self.floor, &self.wall);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{floor, tiles, wall};
+
+    fn cave(seed: u64) -> Grid {
+        let mut grid = Grid::new(32, 24);
+        CaveSculptor::new(floor(), wall(), seed).sculpt_all(&mut grid);
+        grid
+    }
+
+    #[test]
+    fn same_seed_sculpts_same_grid() {
+        for seed in 0..4 {
+            assert_eq!(tiles(&cave(seed)), tiles(&cave(seed)));
+        }
+    }
+}
@@ -1,10 +1,10 @@
 use std::num::NonZeroU16;
 
-use engine::{pos_to_vec2, AsPosition, Grid, MaterialHandle, Position, Rectangle};
+use engine::{pos_to_vec2, AsPosition, Feature, Grid, MaterialHandle, Position, Rectangle};
 use nalgebra_glm::{distance2, vec2};
-use rand::{rngs::ThreadRng, thread_rng, Rng};
+use rand::{Rng, SeedableRng};
 
-use crate::Sculptor;
+use crate::{build_walls, Layout, Sculptor, SculptorRng};
 
 #[non_exhaustive]
 pub struct DungeonSculptor {
@@ -13,11 +13,13 @@ pub struct DungeonSculptor {
 
     floor: MaterialHandle,
     wall: MaterialHandle,
+    // Placed wherever a corridor enters a room
+    door: Option<Feature>,
 
     max_room_size: Position,
     min_room_size: Position,
 
-    rng: ThreadRng,
+    rng: SculptorRng,
 }
 
 impl DungeonSculptor {
@@ -26,6 +28,7 @@ impl DungeonSculptor {
         room_size: (impl AsPosition, impl AsPosition),
         floor: MaterialHandle,
         wall: MaterialHandle,
+        seed: u64,
     ) -> Self {
         Self {
             max_trials: 0xFFFF,
@@ -34,14 +37,58 @@ impl DungeonSculptor {
             room_amount,
             floor,
             wall,
-            rng: thread_rng(),
+            door: None,
+            rng: SculptorRng::seed_from_u64(seed),
         }
     }
+
+    pub fn with_doors(mut self, door: Feature) -> Self {
+        self.door = Some(door);
+        self
+    }
+
+    fn is_floor(&self, grid: &Grid, position: Position) -> bool {
+        grid.get_tile(position)
+            .map_or(false, |tile| tile.material == self.floor)
+    }
+
+    // Tiles right outside the room where a single tile wide corridor leads into it
+    fn entrances(&self, grid: &Grid, room: &Rectangle, rooms: &[Rectangle]) -> Vec<Position> {
+        let (min, max) = (room.min(), room.max());
+
+        let horizontal = (min.x..max.x)
+            .flat_map(|x| [Position::new(x, min.y - 1), Position::new(x, max.y)])
+            .map(|pos| (pos, Position::new(1, 0)));
+        let vertical = (min.y..max.y)
+            .flat_map(|y| [Position::new(min.x - 1, y), Position::new(max.x, y)])
+            .map(|pos| (pos, Position::new(0, 1)));
+
+        horizontal
+            .chain(vertical)
+            .filter(|(pos, along)| {
+                self.is_floor(grid, *pos)
+                    && !self.is_floor(grid, pos - along)
+                    && !self.is_floor(grid, pos + along)
+            })
+            .map(|(pos, _)| pos)
+            .filter(|pos| !rooms.iter().any(|x| x.contains(*pos)))
+            .collect()
+    }
 }
 
 impl Sculptor for DungeonSculptor {
-    #[profiling::function]
     fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        self.sculpt_into(from, to, grid, &mut Layout::default());
+    }
+
+    #[profiling::function]
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        layout: &mut Layout,
+    ) {
         let (from, to) = (from.into(), to.into());
 
         let mut rooms: Vec<Rectangle> = vec![];
@@ -142,38 +189,86 @@ impl Sculptor for DungeonSculptor {
             corridors.push((b, intersection));
         }
 
-        for (from, to) in corridors {
-            grid.make_tile_box(from + Position::new(1, 1), to, self.floor.clone());
-            grid.make_tile_at(from, self.floor.clone());
+        for (from, to) in &corridors {
+            grid.make_tile_box(from + Position::new(1, 1), *to, self.floor.clone());
+            grid.make_tile_at(*from, self.floor.clone());
         }
 
-        for room in rooms {
+        for room in &rooms {
             grid.make_tile_box(room.min(), room.max(), self.floor.clone());
         }
 
-        // lol
-        // TODO: there is a better way, optimize it
-        {
-            profiling::scope!("Wall Generation");
-
-            let mut walls_to_insert = vec![];
-            {
-                profiling::scope!("Locating Walls");
-                for tile in grid.tiles.values() {
-                    if tile.material == self.floor {
-                        for (pos, neighbour) in grid.tile_moore_neighbours(tile.position) {
-                            match neighbour {
-                                Some(_) => continue,
-                                None => walls_to_insert.push(pos),
-                            }
-                        }
-                    }
-                }
-            }
+        build_walls(grid, &self.floor, &self.wall);
 
-            for wall in walls_to_insert {
-                grid.make_tile_at(wall, self.wall.clone());
+        if let Some(door) = self.door {
+            profiling::scope!("Door Placement");
+
+            let entrances: Vec<_> = rooms
+                .iter()
+                .flat_map(|room| self.entrances(grid, room, &rooms))
+                .collect();
+            for entrance in entrances {
+                grid.set_feature(entrance, Some(door))
+                    .expect("Entrances are always on floor tiles");
             }
         }
+
+        layout.rooms.extend(rooms);
+        layout.corridors.extend(corridors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::FeatureState;
+
+    use super::*;
+    use crate::testing::{floor, render, tiles, wall};
+
+    const GOLDEN_DUNGEON: &str = "\
+#####
+#...#
+#...######
+#...+....#
+#...####.#
+#...####+##
+######....#
+     #....#
+     #....#
+     #....#
+     #+###########
+   ###+## #......#
+   #....# #......#
+   #....###......#
+   #....+.+......#
+   #....##########
+   #....#
+   ######";
+
+    fn dungeon(seed: u64) -> Grid {
+        let mut grid = Grid::new(32, 20);
+        DungeonSculptor::new(
+            NonZeroU16::new(4).unwrap(),
+            ([3, 3], [7, 6]),
+            floor(),
+            wall(),
+            seed,
+        )
+        .with_doors(Feature::door(FeatureState::Closed))
+        .sculpt_all(&mut grid);
+        grid
+    }
+
+    #[test]
+    fn same_seed_sculpts_same_grid() {
+        for seed in 0..4 {
+            assert_eq!(tiles(&dungeon(seed)), tiles(&dungeon(seed)));
+        }
+    }
+
+    // Changes to how dungeons are sculpted show up here, update it if they are on purpose
+    #[test]
+    fn golden_dungeon() {
+        assert_eq!(render(&dungeon(7)), GOLDEN_DUNGEON);
     }
 }
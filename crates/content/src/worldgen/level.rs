@@ -0,0 +1,74 @@
+use engine::{Actor, Feature, Grid, LevelGenerator, LevelId, Position};
+
+use crate::{Layout, Sculptor};
+
+type Population = Box<dyn FnMut(LevelId, &Grid) -> Vec<(Position, Actor)>>;
+
+// Generates every new level with the same sculptor,
+// the stairs back are at one end of the level and the stairs further at the other
+pub struct LevelSculptor<S: Sculptor> {
+    sculptor: S,
+    size: (u16, u16),
+    population: Option<Population>,
+    // Actors the sculptor asked for on the level generated last
+    spawns: Vec<(Position, Actor)>,
+}
+
+impl<S: Sculptor> LevelSculptor<S> {
+    pub fn new(sculptor: S, width: u16, height: u16) -> Self {
+        Self {
+            sculptor,
+            size: (width, height),
+            population: None,
+            spawns: vec![],
+        }
+    }
+
+    pub fn with_population(
+        mut self,
+        population: impl FnMut(LevelId, &Grid) -> Vec<(Position, Actor)> + 'static,
+    ) -> Self {
+        self.population = Some(Box::new(population));
+        self
+    }
+}
+
+// Where the stairs back are on levels made by a `LevelSculptor`, the walkable tile lowest down
+pub fn arrival_tile(grid: &Grid) -> Option<Position> {
+    grid.tiles()
+        .values()
+        .filter(|x| x.is_walkable())
+        .map(|x| x.position)
+        .min_by_key(|x| (x.y, x.x))
+}
+
+impl<S: Sculptor> LevelGenerator for LevelSculptor<S> {
+    fn generate(&mut self, _level: LevelId) -> Option<(Grid, Position)> {
+        let mut grid = Grid::new(self.size.0, self.size.1);
+        let mut layout = Layout::default();
+        self.sculptor.sculpt_all_into(&mut grid, &mut layout);
+        self.spawns = layout.spawns;
+
+        let arrival = arrival_tile(&grid)?;
+        let descent = grid
+            .tiles()
+            .values()
+            .filter(|x| x.is_walkable())
+            .map(|x| x.position)
+            .max_by_key(|x| (x.y, x.x))?;
+
+        if descent != arrival {
+            grid.set_feature(descent, Some(Feature::stairs())).ok()?;
+        }
+
+        Some((grid, arrival))
+    }
+
+    fn populate(&mut self, level: LevelId, grid: &Grid) -> Vec<(Position, Actor)> {
+        let mut spawns = std::mem::take(&mut self.spawns);
+        if let Some(population) = &mut self.population {
+            spawns.extend(population(level, grid));
+        }
+        spawns
+    }
+}
@@ -1,8 +1,20 @@
+mod bsp;
+mod cave;
 mod dungeon;
+mod level;
+mod pipeline;
 mod sculptor;
+mod vault;
+mod wfc;
 
+pub use level::*;
+pub use pipeline::*;
 pub use sculptor::*;
 
 pub mod sculptors {
+    pub use super::bsp::*;
+    pub use super::cave::*;
     pub use super::dungeon::*;
+    pub use super::vault::*;
+    pub use super::wfc::*;
 }
@@ -0,0 +1,301 @@
+use engine::{AsPosition, Grid, LightSource, Position, Tile};
+
+use crate::{Layout, Sculptor};
+
+// Ways to put sculptors together, so a whole level can be sculpted by one of them
+pub trait SculptorExt: Sculptor + Sized {
+    // Sculpts the same region with `next` once this one is done, sharing the layout
+    fn then<S: Sculptor>(self, next: S) -> Then<Self, S> {
+        Then {
+            first: self,
+            second: next,
+        }
+    }
+
+    // Only keeps the changes on positions the mask lets through, and the walls around kept floor
+    fn masked<F>(self, mask: F) -> Masked<Self, F>
+    where
+        F: FnMut(&Layout, Position) -> bool,
+    {
+        Masked {
+            sculptor: self,
+            mask,
+        }
+    }
+
+    // Leaves the tiles that were already there alone if `protected` picks them
+    fn protecting<F>(self, protected: F) -> Protecting<Self, F>
+    where
+        F: FnMut(&Tile) -> bool,
+    {
+        Protecting {
+            sculptor: self,
+            protected,
+        }
+    }
+}
+
+impl<S: Sculptor> SculptorExt for S {}
+
+pub struct Then<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Sculptor, B: Sculptor> Sculptor for Then<A, B> {
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        // The second one still gets to build on what the first one made
+        self.sculpt_into(from, to, grid, &mut Layout::default());
+    }
+
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        layout: &mut Layout,
+    ) {
+        let (from, to): (Position, Position) = (from.into(), to.into());
+        self.first.sculpt_into(from, to, grid, layout);
+        self.second.sculpt_into(from, to, grid, layout);
+    }
+}
+
+pub struct Masked<S, F> {
+    sculptor: S,
+    mask: F,
+}
+
+impl<S, F> Sculptor for Masked<S, F>
+where
+    S: Sculptor,
+    F: FnMut(&Layout, Position) -> bool,
+{
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        self.sculpt_into(from, to, grid, &mut Layout::default());
+    }
+
+    #[profiling::function]
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        layout: &mut Layout,
+    ) {
+        let mask = &mut self.mask;
+        sculpt_guarded(
+            &mut self.sculptor,
+            (from.into(), to.into()),
+            grid,
+            layout,
+            |layout, position, _| mask(layout, position),
+        );
+    }
+}
+
+pub struct Protecting<S, F> {
+    sculptor: S,
+    protected: F,
+}
+
+impl<S, F> Sculptor for Protecting<S, F>
+where
+    S: Sculptor,
+    F: FnMut(&Tile) -> bool,
+{
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        self.sculpt_into(from, to, grid, &mut Layout::default());
+    }
+
+    #[profiling::function]
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        layout: &mut Layout,
+    ) {
+        let protected = &mut self.protected;
+        sculpt_guarded(
+            &mut self.sculptor,
+            (from.into(), to.into()),
+            grid,
+            layout,
+            |_, _, tile| !tile.map_or(false, &mut *protected),
+        );
+    }
+}
+
+// A stage that works on the layout as much as on the grid, e.g. to fill the rooms with actors
+pub struct Stage<F> {
+    stage: F,
+}
+
+impl<F> Stage<F>
+where
+    F: FnMut(&mut Grid, &mut Layout, Position, Position),
+{
+    pub fn new(stage: F) -> Self {
+        Self { stage }
+    }
+}
+
+impl<F> Sculptor for Stage<F>
+where
+    F: FnMut(&mut Grid, &mut Layout, Position, Position),
+{
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        self.sculpt_into(from, to, grid, &mut Layout::default());
+    }
+
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        layout: &mut Layout,
+    ) {
+        (self.stage)(grid, layout, from.into(), to.into());
+    }
+}
+
+// Sculpts a copy of the grid and only carries over the changes `keep` allows.
+// `keep` is asked about the layout and tiles from before, new spawns and lights are judged
+// the same way. Floor that is let through keeps the walls around it, even where `keep` refuses them.
+fn sculpt_guarded(
+    sculptor: &mut impl Sculptor,
+    (from, to): (Position, Position),
+    grid: &mut Grid,
+    layout: &mut Layout,
+    mut keep: impl FnMut(&Layout, Position, Option<&Tile>) -> bool,
+) {
+    let mut scratch = grid.clone();
+    // Whatever was pending belongs to the grid, not the copy
+    scratch.take_events();
+    let before = layout.clone();
+
+    sculptor.sculpt_into(from, to, &mut scratch, layout);
+
+    let mut changed: Vec<&Tile> = scratch
+        .tiles()
+        .values()
+        .filter(|x| grid.get_tile(x.position) != Some(*x))
+        .collect();
+    // Sorted, so a mask that keeps track of what it let through sees the same order every time
+    changed.sort_by_key(|x| (x.position.y, x.position.x));
+
+    let (kept, refused): (Vec<&Tile>, Vec<&Tile>) = changed
+        .into_iter()
+        .partition(|x| keep(&before, x.position, grid.get_tile(x.position)));
+
+    // Whatever the sculptor put into the void that can't be walked on is taken to be its wall
+    let wall = kept
+        .iter()
+        .chain(&refused)
+        .find(|x| !x.material.is_walkable() && grid.get_tile(x.position).is_none())
+        .map(|x| x.material.clone());
+    let kept_floor: Vec<Position> = kept
+        .iter()
+        .filter(|x| x.material.is_walkable())
+        .map(|x| x.position)
+        .collect();
+
+    let spawns: Vec<_> = layout.spawns.drain(before.spawns.len()..).collect();
+    layout.spawns.extend(
+        spawns
+            .into_iter()
+            .filter(|(position, _)| keep(&before, *position, grid.get_tile(*position))),
+    );
+
+    let placed: Vec<(Position, LightSource)> = scratch
+        .lights()
+        .filter(|(position, light)| grid.lights().all(|x| x != (*position, *light)))
+        .filter(|(position, _)| keep(&before, *position, grid.get_tile(*position)))
+        .map(|(position, light)| (position, *light))
+        .collect();
+    let removed: Vec<Position> = grid
+        .lights()
+        .map(|(position, _)| position)
+        .filter(|x| scratch.lights().all(|(position, _)| position != *x))
+        .filter(|x| keep(&before, *x, grid.get_tile(*x)))
+        .collect();
+
+    for tile in kept {
+        let (_, made) = grid.make_tile_at(tile.position, tile.material.clone());
+        if made.feature != tile.feature {
+            grid.set_feature(tile.position, tile.feature)
+                .expect("The tile was just made");
+        }
+    }
+
+    // Floor that was let through is walled in wherever it would open onto the void,
+    // with the walls the sculptor made there if there are any
+    if let Some(wall) = wall {
+        for position in kept_floor {
+            let void: Vec<Position> = grid
+                .tile_moore_neighbours(position)
+                .into_iter()
+                .filter(|(_, tile)| tile.is_none())
+                .map(|(x, _)| x)
+                .collect();
+
+            for neighbour in void {
+                let material = scratch
+                    .get_tile(neighbour)
+                    .filter(|x| !x.material.is_walkable())
+                    .map_or_else(|| wall.clone(), |x| x.material.clone());
+                grid.make_tile_at(neighbour, material);
+            }
+        }
+    }
+
+    for (position, light) in placed {
+        grid.place_light(position, light);
+    }
+    for position in removed {
+        grid.remove_light(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sculptors::{BspSculptor, CaveSculptor, DungeonSculptor},
+        testing::{floor, tiles, wall},
+    };
+
+    fn pipeline(seed: u64) -> (Grid, Layout) {
+        let mut grid = Grid::new(48, 32);
+        let mut layout = Layout::default();
+        BspSculptor::new([10, 10], floor(), wall(), seed)
+            .then(
+                CaveSculptor::new(floor(), wall(), seed)
+                    .masked(|layout: &Layout, x| !layout.in_room(x)),
+            )
+            .then(
+                DungeonSculptor::new(
+                    std::num::NonZeroU16::new(2).unwrap(),
+                    ([3, 3], [5, 5]),
+                    floor(),
+                    wall(),
+                    seed,
+                )
+                .protecting(|x: &Tile| x.material.is_walkable()),
+            )
+            .sculpt_all_into(&mut grid, &mut layout);
+        (grid, layout)
+    }
+
+    #[test]
+    fn same_seed_sculpts_same_grid() {
+        for seed in 0..4 {
+            let (grid, layout) = pipeline(seed);
+            let (again, layout_again) = pipeline(seed);
+            assert_eq!(layout.rooms, layout_again.rooms);
+            assert_eq!(layout.corridors, layout_again.corridors);
+            assert_eq!(tiles(&grid), tiles(&again));
+        }
+    }
+}
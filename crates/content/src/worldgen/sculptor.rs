@@ -1,4 +1,36 @@
-use engine::{AsPosition, Grid, Position};
+use engine::{Actor, AsPosition, Grid, MaterialHandle, Position, Rectangle};
+use rand_chacha::ChaCha8Rng;
+
+// Every sculptor draws from it, so the same seed always sculpts the same grid
+pub type SculptorRng = ChaCha8Rng;
+
+// What the stages of a pipeline made so far, for the later ones to build on
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct Layout {
+    pub rooms: Vec<Rectangle>,
+    // Straight runs of floor, from one end to the other
+    pub corridors: Vec<(Position, Position)>,
+    pub vaults: Vec<Rectangle>,
+    // Actors to spawn once the grid is in a world
+    pub spawns: Vec<(Position, Actor)>,
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn in_room(&self, position: impl AsPosition) -> bool {
+        let position = position.into();
+        self.rooms.iter().any(|x| x.contains(position))
+    }
+
+    pub fn in_vault(&self, position: impl AsPosition) -> bool {
+        let position = position.into();
+        self.vaults.iter().any(|x| x.contains(position))
+    }
+}
 
 pub trait Sculptor {
     fn sculpt_all(&mut self, grid: &mut Grid) {
@@ -6,6 +38,26 @@ pub trait Sculptor {
     }
 
     fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid);
+
+    // Same as `sculpt`, but notes down what was made for whatever sculpts next
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        _layout: &mut Layout,
+    ) {
+        self.sculpt(from, to, grid);
+    }
+
+    fn sculpt_all_into(&mut self, grid: &mut Grid, layout: &mut Layout) {
+        self.sculpt_into(
+            <[i32; 2] as Into<Position>>::into([0, 0]),
+            grid.size,
+            grid,
+            layout,
+        );
+    }
 }
 
 impl<F> Sculptor for F
@@ -16,3 +68,83 @@ where
         self(grid, from.into(), to.into())
     }
 }
+
+// Puts a wall on every empty tile next to the floor
+pub(crate) fn build_walls(grid: &mut Grid, floor: &MaterialHandle, wall: &MaterialHandle) {
+    // lol
+    // TODO: there is a better way, optimize it
+    profiling::scope!("Wall Generation");
+
+    let mut walls_to_insert = vec![];
+    {
+        profiling::scope!("Locating Walls");
+        for tile in grid.tiles().values() {
+            if tile.material == *floor {
+                for (pos, neighbour) in grid.tile_moore_neighbours(tile.position) {
+                    match neighbour {
+                        Some(_) => continue,
+                        None => walls_to_insert.push(pos),
+                    }
+                }
+            }
+        }
+    }
+
+    for position in walls_to_insert {
+        grid.make_tile_at(position, wall.clone());
+    }
+}
+
+// What the tests of every sculptor share
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::rc::Rc;
+
+    use engine::{FeatureState, Grid, Material, MaterialFlags, MaterialHandle, Tile};
+
+    pub fn floor() -> MaterialHandle {
+        Rc::new(Material::new(
+            "Floor",
+            "floor",
+            None::<String>,
+            MaterialFlags::FLOOR,
+        ))
+    }
+
+    pub fn wall() -> MaterialHandle {
+        Rc::new(Material::new(
+            "Wall",
+            "wall",
+            None::<String>,
+            MaterialFlags::WALL,
+        ))
+    }
+
+    // Every tile of the grid, row by row from the bottom
+    pub fn tiles(grid: &Grid) -> Vec<Tile> {
+        let mut tiles: Vec<Tile> = grid.tiles().values().cloned().collect();
+        tiles.sort_by_key(|x| (x.position.y, x.position.x));
+        tiles
+    }
+
+    // The grid in ASCII with the top row first, `+` for closed doors and `'` for open ones.
+    // Empty rows at the bottom are left out.
+    pub fn render(grid: &Grid) -> String {
+        let mut lines = vec![];
+        for y in (0..grid.size.y).rev() {
+            let line: String = (0..grid.size.x)
+                .map(|x| match grid.get_tile([x, y]) {
+                    None => ' ',
+                    Some(tile) => match tile.feature.map(|x| x.state) {
+                        Some(FeatureState::Open) => '\'',
+                        Some(_) => '+',
+                        None if tile.material.is_walkable() => '.',
+                        None => '#',
+                    },
+                })
+                .collect();
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n").trim_end().to_string()
+    }
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use engine::{
+    Actor, ActorTemplate, AsPosition, Feature, Grid, MaterialHandle, Position, Rectangle,
+};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::{Layout, Sculptor, SculptorRng};
+
+// Random spots tried for every vault before giving up on it
+pub const DEFAULT_PLACEMENT_ATTEMPTS: u32 = 256;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum VaultError {
+    #[error("the vault has no tiles")]
+    Empty,
+    #[error("'{char}' on line {line}, column {column} isn't in the legend")]
+    UnknownChar {
+        char: char,
+        line: usize,
+        column: usize,
+    },
+}
+
+// What a character of a vault turns into, anything left out stays as it was
+#[derive(Debug, Default, Clone)]
+pub struct Glyph {
+    pub material: Option<MaterialHandle>,
+    pub feature: Option<Feature>,
+    pub actor: Option<Rc<ActorTemplate>>,
+}
+
+impl Glyph {
+    pub fn new(material: MaterialHandle) -> Self {
+        Self {
+            material: Some(material),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_feature(mut self, feature: Feature) -> Self {
+        self.feature = Some(feature);
+        self
+    }
+
+    pub fn with_actor(mut self, actor: Rc<ActorTemplate>) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+}
+
+// Characters vaults are drawn with, a space always leaves the tile alone
+#[derive(Debug, Default, Clone)]
+pub struct Legend {
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl Legend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, char: char, glyph: Glyph) -> Self {
+        self.glyphs.insert(char, glyph);
+        self
+    }
+
+    pub fn glyph(&self, char: char) -> Option<&Glyph> {
+        self.glyphs.get(&char)
+    }
+}
+
+// A hand-made set piece, drawn in ASCII with the first line as the top row
+#[derive(Debug, Clone)]
+pub struct Vault {
+    size: Position,
+    // The bottom row first
+    cells: Vec<Option<Glyph>>,
+    rotatable: bool,
+    mirrorable: bool,
+}
+
+impl Vault {
+    pub fn parse(text: &str, legend: &Legend) -> Result<Self, VaultError> {
+        let lines: Vec<&str> = text.lines().collect();
+        let width = lines.iter().map(|x| x.chars().count()).max().unwrap_or(0);
+        let height = lines.len();
+        if width == 0 {
+            return Err(VaultError::Empty);
+        }
+
+        let mut cells = vec![None; width * height];
+        for (line_index, line) in lines.iter().enumerate() {
+            let y = height - 1 - line_index;
+            for (x, char) in line.chars().enumerate() {
+                if char == ' ' {
+                    continue;
+                }
+
+                let glyph = legend.glyph(char).ok_or(VaultError::UnknownChar {
+                    char,
+                    line: line_index + 1,
+                    column: x + 1,
+                })?;
+                cells[y * width + x] = Some(glyph.clone());
+            }
+        }
+
+        Ok(Self {
+            size: Position::new(width as i32, height as i32),
+            cells,
+            rotatable: true,
+            mirrorable: true,
+        })
+    }
+
+    // Whether the vault can be turned by quarter turns, on by default
+    pub fn with_rotation(mut self, rotatable: bool) -> Self {
+        self.rotatable = rotatable;
+        self
+    }
+
+    // Whether the vault can be flipped left to right, on by default
+    pub fn with_mirroring(mut self, mirrorable: bool) -> Self {
+        self.mirrorable = mirrorable;
+        self
+    }
+
+    pub fn size(&self) -> Position {
+        self.size
+    }
+
+    pub fn glyph(&self, position: impl AsPosition) -> Option<&Glyph> {
+        let position = position.into();
+        if position.x < 0
+            || position.y < 0
+            || position.x >= self.size.x
+            || position.y >= self.size.y
+        {
+            return None;
+        }
+        self.cells[(position.y * self.size.x + position.x) as usize].as_ref()
+    }
+
+    // Turned a quarter turn counterclockwise
+    fn rotated(&self) -> Self {
+        self.remapped(Position::new(self.size.y, self.size.x), |x, size| {
+            Position::new(x.y, size.x - 1 - x.x)
+        })
+    }
+
+    fn mirrored(&self) -> Self {
+        self.remapped(self.size, |x, size| Position::new(size.x - 1 - x.x, x.y))
+    }
+
+    // Builds a vault of the new size where every cell comes from the old position `from` gives
+    fn remapped(&self, size: Position, from: impl Fn(Position, Position) -> Position) -> Self {
+        let cells = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| Position::new(x, y)))
+            .map(|x| self.glyph(from(x, size)).cloned())
+            .collect();
+
+        Self {
+            size,
+            cells,
+            ..self.clone()
+        }
+    }
+
+    // Every way the vault can be placed
+    fn variants(&self) -> Vec<Vault> {
+        let mut variants = vec![self.clone()];
+        if self.rotatable {
+            for _ in 0..3 {
+                let next = variants
+                    .last()
+                    .expect("There is always a variant")
+                    .rotated();
+                variants.push(next);
+            }
+        }
+        if self.mirrorable {
+            let mirrored: Vec<_> = variants.iter().map(Vault::mirrored).collect();
+            variants.extend(mirrored);
+        }
+        variants
+    }
+
+    fn footprint(&self, at: Position) -> impl Iterator<Item = (Position, &Glyph)> {
+        (0..self.size.y)
+            .flat_map(|y| (0..self.size.x).map(move |x| Position::new(x, y)))
+            .filter_map(move |x| Some((at + x, self.glyph(x)?)))
+    }
+}
+
+// Stamps vaults onto spots of the grid where they fit and don't cut anything off
+#[non_exhaustive]
+pub struct VaultSculptor {
+    vaults: Vec<Vault>,
+    // How many vaults every sculpt tries to place
+    amount: u32,
+    attempts: u32,
+    // Actors the placed vaults asked for, the grid itself can't hold them
+    spawns: Vec<(Position, Actor)>,
+
+    rng: SculptorRng,
+}
+
+impl VaultSculptor {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            vaults: vec![],
+            amount: 1,
+            attempts: DEFAULT_PLACEMENT_ATTEMPTS,
+            spawns: vec![],
+            rng: SculptorRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn with_vault(mut self, vault: Vault) -> Self {
+        self.vaults.push(vault);
+        self
+    }
+
+    pub fn with_amount(mut self, amount: u32) -> Self {
+        self.amount = amount;
+        self
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    // Actors of every vault placed so far, to be spawned once the grid is in a world
+    pub fn take_spawns(&mut self) -> Vec<(Position, Actor)> {
+        std::mem::take(&mut self.spawns)
+    }
+
+    fn fits(
+        &self,
+        grid: &Grid,
+        region: &Rectangle,
+        vault: &Vault,
+        at: Position,
+        taken: &HashSet<Position>,
+    ) -> bool {
+        let (min, max) = (region.min(), region.max());
+
+        vault.footprint(at).all(|(position, glyph)| {
+            let inside = position.x >= min.x.max(0)
+                && position.y >= min.y.max(0)
+                && position.x < max.x.min(grid.size.x)
+                && position.y < max.y.min(grid.size.y);
+            let tile = grid.get_tile(position);
+
+            // Stairs and whoever stands on the tile stay where they are
+            let is_free = tile.map_or(true, |x| x.feature.is_none() && x.occupier.is_none());
+            // Features and actors on their own need a tile to be on
+            let has_tile = glyph.material.is_some() || tile.is_some();
+
+            inside && is_free && has_tile && !taken.contains(&position)
+        })
+    }
+
+    // Whether placing the vault leaves every walkable tile reachable from the others that were.
+    // Takes the walkable tiles of the grid and the groups they make up.
+    fn keeps_connected(
+        grid: &Grid,
+        (walkable, before): (&HashSet<Position>, &[Vec<Position>]),
+        vault: &Vault,
+        at: Position,
+    ) -> bool {
+        let mut walkable = walkable.clone();
+        let mut stamped = HashSet::new();
+        for (position, glyph) in vault.footprint(at) {
+            let tile = grid.get_tile(position);
+            let Some(material) = glyph.material.as_ref().or(tile.map(|x| &x.material)) else {
+                continue;
+            };
+            let feature = match glyph.material {
+                Some(_) => glyph.feature,
+                None => glyph.feature.or(tile.and_then(|x| x.feature)),
+            };
+
+            stamped.insert(position);
+            if is_open(material, feature) {
+                walkable.insert(position);
+            } else {
+                walkable.remove(&position);
+            }
+        }
+
+        let after = components(&walkable);
+        let group: HashMap<Position, usize> = after
+            .iter()
+            .enumerate()
+            .flat_map(|(index, x)| x.iter().map(move |x| (*x, index)))
+            .collect();
+
+        // Whatever is left of a group has to stay together, merging groups is fine
+        let is_split = before.iter().any(|x| {
+            let mut groups = x.iter().filter_map(|x| group.get(x));
+            groups
+                .next()
+                .map_or(false, |first| groups.any(|x| x != first))
+        });
+        // A vault that only connects to itself is as bad as one that cuts a corridor
+        !is_split && after.iter().all(|x| x.iter().any(|x| !stamped.contains(x)))
+    }
+
+    fn stamp(&mut self, grid: &mut Grid, vault: &Vault, at: Position) {
+        for (position, glyph) in vault.footprint(at) {
+            if let Some(material) = &glyph.material {
+                grid.make_tile_at(position, material.clone());
+            }
+            if let Some(feature) = glyph.feature {
+                grid.set_feature(position, Some(feature))
+                    .expect("Vaults are only placed where every feature has a tile");
+            }
+            if let Some(actor) = &glyph.actor {
+                self.spawns
+                    .push((position, Actor::from_template(actor.clone())));
+            }
+        }
+    }
+
+    // Places the vaults and returns where they ended up
+    fn place(&mut self, from: Position, to: Position, grid: &mut Grid) -> Vec<Rectangle> {
+        let mut placed = vec![];
+        if self.vaults.is_empty() {
+            return placed;
+        }
+
+        let region = Rectangle::new(from, to);
+        let variants: Vec<Vec<Vault>> = self.vaults.iter().map(Vault::variants).collect();
+        let mut taken = HashSet::new();
+
+        for _ in 0..self.amount {
+            let walkable: HashSet<Position> = grid
+                .tiles()
+                .values()
+                .filter(|x| is_open(&x.material, x.feature))
+                .map(|x| x.position)
+                .collect();
+            let before = components(&walkable);
+
+            let choices = &variants[self.rng.gen_range(0..variants.len())];
+
+            for _ in 0..self.attempts {
+                let vault = choices
+                    .choose(&mut self.rng)
+                    .expect("Every vault has a variant");
+                let (min, max) = (region.min(), region.max() - vault.size());
+                if max.x < min.x || max.y < min.y {
+                    continue;
+                }
+
+                let at = Position::new(
+                    self.rng.gen_range(min.x..=max.x),
+                    self.rng.gen_range(min.y..=max.y),
+                );
+                if !self.fits(grid, &region, vault, at, &taken)
+                    || !Self::keeps_connected(grid, (&walkable, &before), vault, at)
+                {
+                    continue;
+                }
+
+                taken.extend(vault.footprint(at).map(|(x, _)| x));
+                self.stamp(grid, vault, at);
+                placed.push(Rectangle::new(at, at + vault.size()));
+                break;
+            }
+        }
+
+        placed
+    }
+}
+
+impl Sculptor for VaultSculptor {
+    #[profiling::function]
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        self.place(from.into(), to.into(), grid);
+    }
+
+    // The actors go into the layout instead of waiting for `take_spawns`
+    #[profiling::function]
+    fn sculpt_into(
+        &mut self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        grid: &mut Grid,
+        layout: &mut Layout,
+    ) {
+        let waiting = self.spawns.len();
+        let placed = self.place(from.into(), to.into(), grid);
+        layout.vaults.extend(placed);
+        layout.spawns.extend(self.spawns.drain(waiting..));
+    }
+}
+
+// Tiles actors can get through, doors count as long as they can be opened
+fn is_open(material: &MaterialHandle, feature: Option<Feature>) -> bool {
+    material.is_walkable()
+        && feature.map_or(true, |x| !x.blocks_movement() || x.interacted().is_some())
+}
+
+// Groups of tiles that can reach each other by orthogonal steps
+fn components(walkable: &HashSet<Position>) -> Vec<Vec<Position>> {
+    let mut seen = HashSet::new();
+    let mut components = vec![];
+
+    for start in walkable {
+        if !seen.insert(*start) {
+            continue;
+        }
+
+        let mut component = vec![];
+        let mut stack = vec![*start];
+        while let Some(position) = stack.pop() {
+            component.push(position);
+            for step in [[1, 0], [-1, 0], [0, 1], [0, -1]] {
+                let next = position + Position::from(step);
+                if walkable.contains(&next) && seen.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        components.push(component);
+    }
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sculptors::BspSculptor,
+        testing::{floor, tiles, wall},
+    };
+
+    const FOUNTAIN: &str = "\
+...
+.f.
+...";
+
+    fn vaults(seed: u64) -> (Grid, Vec<Rectangle>) {
+        let mut grid = Grid::new(40, 30);
+        BspSculptor::new([8, 8], floor(), wall(), 0).sculpt_all(&mut grid);
+
+        let legend = Legend::new()
+            .with('.', Glyph::new(floor()))
+            .with('f', Glyph::new(floor()).with_feature(Feature::fountain()));
+        let vault = Vault::parse(FOUNTAIN, &legend).expect("The vault is in the legend");
+
+        let mut layout = Layout::default();
+        VaultSculptor::new(seed)
+            .with_vault(vault)
+            .with_amount(3)
+            .sculpt_all_into(&mut grid, &mut layout);
+        (grid, layout.vaults)
+    }
+
+    #[test]
+    fn same_seed_sculpts_same_grid() {
+        for seed in 0..4 {
+            let (grid, placed) = vaults(seed);
+            let (again, placed_again) = vaults(seed);
+            assert!(!placed.is_empty());
+            assert_eq!(placed, placed_again);
+            assert_eq!(tiles(&grid), tiles(&again));
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+
+use engine::{AsPosition, Grid, MaterialHandle, Position};
+use rand::{Rng, SeedableRng};
+
+use crate::{Sculptor, SculptorRng};
+
+// Side of the square patterns learned from the sample
+pub const DEFAULT_PATTERN_SIZE: usize = 3;
+// How many wrong guesses get undone before giving up on the region
+pub const DEFAULT_MAX_BACKTRACKS: u32 = 4096;
+
+// Right, up, left and down, so the opposite of a direction is two steps away
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+fn opposite(direction: usize) -> usize {
+    (direction + 2) % 4
+}
+
+// Fills the region in the style of a small hand-drawn sample using the overlapping
+// Wave Function Collapse model. Every square of the output looks like some square of the sample.
+#[non_exhaustive]
+pub struct WfcSculptor {
+    // What every character of the sample stands for, None leaves the tile alone
+    tiles: Vec<Option<MaterialHandle>>,
+    // The sample by index into `tiles`, the bottom row first
+    sample: Vec<usize>,
+    sample_size: (usize, usize),
+
+    pattern_size: usize,
+    // Also learns every rotation and reflection of the patterns
+    symmetric: bool,
+    // Patterns continue over the edges of the sample onto the other side
+    wrapping: bool,
+    max_backtracks: u32,
+
+    rng: SculptorRng,
+}
+
+impl WfcSculptor {
+    // The first line of the sample is the top row. Characters missing from the legend
+    // are learned like any other, but no tile is made where they end up.
+    pub fn new(
+        sample: &str,
+        legend: impl IntoIterator<Item = (char, MaterialHandle)>,
+        seed: u64,
+    ) -> Self {
+        let legend: HashMap<char, MaterialHandle> = legend.into_iter().collect();
+
+        let lines: Vec<Vec<char>> = sample
+            .lines()
+            .rev()
+            .map(|line| line.chars().collect())
+            .collect();
+        let width = lines.iter().map(Vec::len).max().unwrap_or(0);
+        let height = lines.len();
+
+        let mut chars = vec![];
+        let mut tiles = vec![];
+        let mut indices = Vec::with_capacity(width * height);
+        for line in &lines {
+            for x in 0..width {
+                let char = line.get(x).copied().unwrap_or(' ');
+                let index = match chars.iter().position(|x| *x == char) {
+                    Some(index) => index,
+                    None => {
+                        chars.push(char);
+                        tiles.push(legend.get(&char).cloned());
+                        chars.len() - 1
+                    }
+                };
+                indices.push(index);
+            }
+        }
+
+        Self {
+            tiles,
+            sample: indices,
+            sample_size: (width, height),
+            pattern_size: DEFAULT_PATTERN_SIZE,
+            symmetric: false,
+            wrapping: false,
+            max_backtracks: DEFAULT_MAX_BACKTRACKS,
+            rng: SculptorRng::seed_from_u64(seed),
+        }
+    }
+
+    // Bigger patterns copy more of the sample, but need bigger samples to not get stuck
+    pub fn with_pattern_size(mut self, pattern_size: usize) -> Self {
+        self.pattern_size = pattern_size.max(1);
+        self
+    }
+
+    // Rotated and mirrored copies of the sample count too, for more variety out of a small sample
+    pub fn with_symmetry(mut self, symmetric: bool) -> Self {
+        self.symmetric = symmetric;
+        self
+    }
+
+    // For samples that tile, so patterns crossing their edges can be learned too
+    pub fn with_wrapping(mut self, wrapping: bool) -> Self {
+        self.wrapping = wrapping;
+        self
+    }
+
+    pub fn with_max_backtracks(mut self, max_backtracks: u32) -> Self {
+        self.max_backtracks = max_backtracks;
+        self
+    }
+}
+
+// Patterns of the sample and which of them can overlap
+struct Model {
+    size: usize,
+    // Tiles of each pattern, the bottom row first
+    patterns: Vec<Vec<usize>>,
+    // How often each pattern appears in the sample
+    weights: Vec<f64>,
+    // Patterns that can be next to a pattern in a direction, by direction and pattern
+    propagator: [Vec<Vec<usize>>; 4],
+}
+
+impl Model {
+    fn learn(
+        sample: &[usize],
+        (width, height): (usize, usize),
+        size: usize,
+        symmetric: bool,
+        wrapping: bool,
+    ) -> Self {
+        let mut patterns: Vec<Vec<usize>> = vec![];
+        let mut weights = vec![];
+        let mut known = HashMap::new();
+
+        let (last_x, last_y) = if wrapping {
+            (width, height)
+        } else {
+            (
+                (width + 1).saturating_sub(size),
+                (height + 1).saturating_sub(size),
+            )
+        };
+
+        for y in 0..last_y {
+            for x in 0..last_x {
+                let pattern: Vec<usize> = (0..size)
+                    .flat_map(|dy| (0..size).map(move |dx| (x + dx, y + dy)))
+                    .map(|(x, y)| sample[(y % height) * width + x % width])
+                    .collect();
+
+                let variants = if symmetric {
+                    Self::symmetries(pattern, size)
+                } else {
+                    vec![pattern]
+                };
+
+                for pattern in variants {
+                    match known.get(&pattern) {
+                        Some(index) => weights[*index] += 1.,
+                        None => {
+                            known.insert(pattern.clone(), patterns.len());
+                            patterns.push(pattern);
+                            weights.push(1.);
+                        }
+                    }
+                }
+            }
+        }
+
+        let propagator = DIRECTIONS.map(|(dx, dy)| {
+            patterns
+                .iter()
+                .map(|a| {
+                    (0..patterns.len())
+                        .filter(|b| Self::agrees(a, &patterns[*b], size, dx, dy))
+                        .collect()
+                })
+                .collect()
+        });
+
+        Self {
+            size,
+            patterns,
+            weights,
+            propagator,
+        }
+    }
+
+    // The pattern turned four ways, each as it is and mirrored
+    fn symmetries(pattern: Vec<usize>, size: usize) -> Vec<Vec<usize>> {
+        let rotated = |x: &Vec<usize>| -> Vec<usize> {
+            (0..size * size)
+                .map(|i| x[(i % size) * size + (size - 1 - i / size)])
+                .collect()
+        };
+        let mirrored = |x: &Vec<usize>| -> Vec<usize> {
+            (0..size * size)
+                .map(|i| x[(i / size) * size + (size - 1 - i % size)])
+                .collect()
+        };
+
+        let mut variants = vec![pattern];
+        for _ in 0..3 {
+            variants.push(rotated(variants.last().expect("There is always a pattern")));
+        }
+        let mirrors: Vec<_> = variants.iter().map(mirrored).collect();
+        variants.extend(mirrors);
+        variants
+    }
+
+    // Whether `b` moved by the offset matches `a` wherever they overlap
+    fn agrees(a: &[usize], b: &[usize], size: usize, dx: i32, dy: i32) -> bool {
+        let size = size as i32;
+        let (xs, ys) = (
+            dx.max(0)..(size + dx).min(size),
+            dy.max(0)..(size + dy).min(size),
+        );
+
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .all(|(x, y)| a[(y * size + x) as usize] == b[((y - dy) * size + (x - dx)) as usize])
+    }
+}
+
+// Patterns every cell of the output can still be
+struct Wave<'a> {
+    model: &'a Model,
+    width: usize,
+    height: usize,
+
+    // By cell and then pattern
+    possible: Vec<bool>,
+    remaining: Vec<usize>,
+    // Patterns of the neighbour in a direction that still allow the pattern, by cell and pattern
+    support: Vec<[usize; 4]>,
+    // Every pattern ruled out so far, in order, so guesses can be undone
+    trail: Vec<(usize, usize)>,
+    // Patterns that lost all support and are yet to be ruled out
+    queue: Vec<(usize, usize)>,
+    // Some cell has nothing left since the last undo
+    contradiction: bool,
+    backtracks: u32,
+}
+
+impl<'a> Wave<'a> {
+    fn new(model: &'a Model, width: usize, height: usize) -> Self {
+        let patterns = model.patterns.len();
+        let support: Vec<[usize; 4]> = (0..patterns)
+            .map(|pattern| [0, 1, 2, 3].map(|x| model.propagator[x][pattern].len()))
+            .collect();
+
+        Self {
+            model,
+            width,
+            height,
+            possible: vec![true; width * height * patterns],
+            remaining: vec![patterns; width * height],
+            support: support.repeat(width * height),
+            trail: vec![],
+            queue: vec![],
+            contradiction: false,
+            backtracks: 0,
+        }
+    }
+
+    fn neighbour(&self, cell: usize, direction: usize) -> Option<usize> {
+        let (dx, dy) = DIRECTIONS[direction];
+        let x = (cell % self.width) as i32 + dx;
+        let y = (cell / self.width) as i32 + dy;
+
+        if !(0..self.width as i32).contains(&x) || !(0..self.height as i32).contains(&y) {
+            return None;
+        }
+        Some(y as usize * self.width + x as usize)
+    }
+
+    // Takes the pattern away from the neighbours' support right away, so every entry
+    // of the trail can be given back in `undo` whether it got propagated or not
+    fn ban(&mut self, cell: usize, pattern: usize) {
+        let patterns = self.model.patterns.len();
+        let index = cell * patterns + pattern;
+        if !self.possible[index] {
+            return;
+        }
+
+        self.possible[index] = false;
+        self.remaining[cell] -= 1;
+        if self.remaining[cell] == 0 {
+            self.contradiction = true;
+        }
+        self.trail.push((cell, pattern));
+
+        for direction in 0..4 {
+            let Some(neighbour) = self.neighbour(cell, direction) else {
+                continue;
+            };
+
+            for &other in &self.model.propagator[direction][pattern] {
+                let support = &mut self.support[neighbour * patterns + other][opposite(direction)];
+                *support -= 1;
+                if *support == 0 && self.possible[neighbour * patterns + other] {
+                    self.queue.push((neighbour, other));
+                }
+            }
+        }
+    }
+
+    // Rules out everything the bans so far lead to, false if some cell has nothing left
+    fn propagate(&mut self) -> bool {
+        while let Some((cell, pattern)) = self.queue.pop() {
+            if self.contradiction {
+                break;
+            }
+            self.ban(cell, pattern);
+        }
+
+        self.queue.clear();
+        !self.contradiction
+    }
+
+    // Brings back everything ruled out since the trail was this long
+    fn undo(&mut self, mark: usize) {
+        let patterns = self.model.patterns.len();
+
+        while self.trail.len() > mark {
+            let (cell, pattern) = self.trail.pop().expect("Trail is longer than the mark");
+            self.possible[cell * patterns + pattern] = true;
+            self.remaining[cell] += 1;
+
+            for direction in 0..4 {
+                if let Some(neighbour) = self.neighbour(cell, direction) {
+                    for &other in &self.model.propagator[direction][pattern] {
+                        self.support[neighbour * patterns + other][opposite(direction)] += 1;
+                    }
+                }
+            }
+        }
+
+        // Marks are only taken while nothing contradicts
+        self.contradiction = false;
+    }
+
+    // Patterns with nothing that could ever be next to them are out from the start
+    fn ban_unsupported(&mut self) -> bool {
+        let patterns = self.model.patterns.len();
+        for cell in 0..self.width * self.height {
+            for pattern in 0..patterns {
+                let unsupported = (0..4).any(|direction| {
+                    self.neighbour(cell, direction).is_some()
+                        && self.support[cell * patterns + pattern][direction] == 0
+                });
+                if unsupported {
+                    self.ban(cell, pattern);
+                }
+            }
+        }
+        self.propagate()
+    }
+
+    // The undecided cell with the fewest options left, ties are broken at random
+    fn most_constrained(&self, rng: &mut SculptorRng) -> Option<usize> {
+        (0..self.width * self.height)
+            .filter(|x| self.remaining[*x] > 1)
+            .map(|x| (self.remaining[x] as f64 + rng.gen_range(0. ..0.5), x))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, x)| x)
+    }
+
+    fn pick(&self, cell: usize, rng: &mut SculptorRng) -> usize {
+        let patterns = self.model.patterns.len();
+        let options: Vec<usize> = (0..patterns)
+            .filter(|x| self.possible[cell * patterns + x])
+            .collect();
+
+        let total: f64 = options.iter().map(|x| self.model.weights[*x]).sum();
+        let mut roll = rng.gen_range(0. ..total);
+        for &option in &options {
+            roll -= self.model.weights[option];
+            if roll < 0. {
+                return option;
+            }
+        }
+        *options.last().expect("Cell has more than one option")
+    }
+
+    fn collapse(&mut self, rng: &mut SculptorRng, max_backtracks: u32) -> bool {
+        if !self.ban_unsupported() {
+            return false;
+        }
+
+        let patterns = self.model.patterns.len();
+        let mut guesses = vec![];
+
+        while let Some(cell) = self.most_constrained(rng) {
+            let guess = self.pick(cell, rng);
+            guesses.push((self.trail.len(), cell, guess));
+            for pattern in (0..patterns).filter(|x| *x != guess) {
+                self.ban(cell, pattern);
+            }
+
+            // A wrong guess is undone and ruled out, which might show the guess before it was wrong too
+            let mut consistent = self.propagate();
+            while !consistent {
+                let Some((mark, cell, guess)) = guesses.pop() else {
+                    return false;
+                };
+
+                self.backtracks += 1;
+                if self.backtracks > max_backtracks {
+                    return false;
+                }
+
+                self.undo(mark);
+                self.ban(cell, guess);
+                consistent = self.propagate();
+            }
+        }
+
+        true
+    }
+
+    fn pattern_at(&self, cell: usize) -> Option<usize> {
+        let patterns = self.model.patterns.len();
+        (0..patterns).find(|x| self.possible[cell * patterns + x])
+    }
+}
+
+impl Sculptor for WfcSculptor {
+    // Leaves the region untouched if the sample can't fill it
+    #[profiling::function]
+    fn sculpt(&mut self, from: impl AsPosition, to: impl AsPosition, grid: &mut Grid) {
+        let (from, to): (Position, Position) = (from.into(), to.into());
+        let size = self.pattern_size;
+        let (width, height) = ((to.x - from.x) as usize, (to.y - from.y) as usize);
+        if to.x < from.x || to.y < from.y || width < size || height < size {
+            return;
+        }
+
+        let model = {
+            profiling::scope!("Learning");
+            Model::learn(
+                &self.sample,
+                self.sample_size,
+                size,
+                self.symmetric,
+                self.wrapping,
+            )
+        };
+        if model.patterns.is_empty() {
+            return;
+        }
+
+        // Every cell is the corner of a pattern, the last ones reach the edge of the region
+        let (cells_x, cells_y) = (width - size + 1, height - size + 1);
+        let mut wave = Wave::new(&model, cells_x, cells_y);
+        {
+            profiling::scope!("Collapsing");
+            if !wave.collapse(&mut self.rng, self.max_backtracks) {
+                return;
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let (cell_x, cell_y) = (x.min(cells_x - 1), y.min(cells_y - 1));
+                let Some(pattern) = wave.pattern_at(cell_y * cells_x + cell_x) else {
+                    continue;
+                };
+
+                let tile = model.patterns[pattern][(y - cell_y) * model.size + (x - cell_x)];
+                if let Some(material) = &self.tiles[tile] {
+                    let position = from + Position::new(x as i32, y as i32);
+                    grid.make_tile_at(position, material.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{floor, tiles, wall};
+
+    // Runs into contradictions often enough that most seeds have to backtrack
+    const SAMPLE: &str = "\
+#...#.
+#...##
+.###..
+#####.
+.#...#
+..##..";
+
+    #[test]
+    fn backtracking_keeps_neighbours_agreeing() {
+        let sculptor = WfcSculptor::new(SAMPLE, std::iter::empty(), 0);
+        let model = Model::learn(&sculptor.sample, sculptor.sample_size, 3, true, false);
+
+        let mut backtracked = false;
+        for seed in 0..8 {
+            let mut rng = SculptorRng::seed_from_u64(seed);
+            let mut wave = Wave::new(&model, 10, 10);
+            if !wave.collapse(&mut rng, DEFAULT_MAX_BACKTRACKS) || wave.backtracks == 0 {
+                continue;
+            }
+            backtracked = true;
+
+            for cell in 0..wave.width * wave.height {
+                let pattern = wave
+                    .pattern_at(cell)
+                    .expect("Collapsed cells have a pattern");
+                for (direction, (dx, dy)) in DIRECTIONS.into_iter().enumerate() {
+                    let Some(neighbour) = wave.neighbour(cell, direction) else {
+                        continue;
+                    };
+                    let other = wave
+                        .pattern_at(neighbour)
+                        .expect("Collapsed cells have a pattern");
+                    assert!(Model::agrees(
+                        &model.patterns[pattern],
+                        &model.patterns[other],
+                        model.size,
+                        dx,
+                        dy
+                    ));
+                }
+            }
+        }
+
+        assert!(backtracked);
+    }
+
+    #[test]
+    fn same_seed_sculpts_same_grid() {
+        let wfc = |seed| {
+            let mut grid = Grid::new(12, 12);
+            WfcSculptor::new(SAMPLE, [('#', wall()), ('.', floor())], seed)
+                .with_pattern_size(3)
+                .sculpt_all(&mut grid);
+            grid
+        };
+
+        for seed in 0..4 {
+            assert_eq!(tiles(&wfc(seed)), tiles(&wfc(seed)));
+        }
+    }
+}
@@ -31,7 +31,7 @@ pub fn min_max_aabb_from_rect(a: impl AsPosition, b: impl AsPosition) -> (Positi
     (a, b)
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Rectangle {
     min: Position,
     max: Position,
@@ -55,6 +55,13 @@ impl Rectangle {
         self.min / 2 + self.max / 2
     }
 
+    // The max corner is outside, like everywhere else rectangles are used
+    pub fn contains(&self, position: impl AsPosition) -> bool {
+        let position = position.into();
+        (self.min.x..self.max.x).contains(&position.x)
+            && (self.min.y..self.max.y).contains(&position.y)
+    }
+
     pub fn overlaps(&self, rhs: &Rectangle) -> bool {
         let (xmax1, xmax2) = (self.max.x, rhs.max.x);
         let (ymax1, ymax2) = (self.max.y, rhs.max.y);
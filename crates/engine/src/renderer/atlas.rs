@@ -207,6 +207,20 @@ impl Atlas {
         let resource_name_to_sprite = HashMap::from_iter([
             ("tile.floor".to_string(), (0, smallvec![])),
             ("tile.wall".to_string(), (1, smallvec![])),
+            // Every `Feature::all()` needs one, states without a sprite of their own
+            // borrow the one that looks closest
+            ("feature.door.open".to_string(), (3, smallvec![])),
+            ("feature.door.closed".to_string(), (2, smallvec![])),
+            ("feature.door.locked".to_string(), (2, smallvec![])),
+            ("feature.lever.open".to_string(), (12, smallvec![])),
+            ("feature.lever.closed".to_string(), (15, smallvec![])),
+            ("feature.lever.locked".to_string(), (15, smallvec![])),
+            ("feature.stairs.open".to_string(), (14, smallvec![])),
+            ("feature.stairs.closed".to_string(), (14, smallvec![])),
+            ("feature.stairs.locked".to_string(), (14, smallvec![])),
+            ("feature.fountain.open".to_string(), (6, smallvec![])),
+            ("feature.fountain.closed".to_string(), (6, smallvec![])),
+            ("feature.fountain.locked".to_string(), (6, smallvec![])),
             ("creature.snek".to_string(), (5, smallvec![])),
             ("creature.player".to_string(), (16, smallvec![])),
         ]);
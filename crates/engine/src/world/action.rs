@@ -1,19 +1,105 @@
-use crate::{ActorReference, AsPosition, Position};
+use thiserror::Error;
 
-#[derive(Debug, Clone, Hash, PartialEq)]
+use crate::{ActorId, AsPosition, Event, LevelId, Position, TURN_ENERGY};
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum Action {
-    MoveActor {
-        actor_ref: ActorReference,
-        to: Position,
-    },
+    MoveActor { actor: ActorId, to: Position },
+    Attack { actor: ActorId, target: ActorId },
+    Wait { actor: ActorId },
+    Interact { actor: ActorId, at: Position },
 }
 
 impl Action {
-    pub fn move_actor(actor: ActorReference, to: impl AsPosition) -> Self {
+    pub fn move_actor(actor: ActorId, to: impl AsPosition) -> Self {
         Action::MoveActor {
-            actor_ref: actor,
+            actor,
             to: to.into(),
         }
     }
+
+    pub fn attack(actor: ActorId, target: ActorId) -> Self {
+        Action::Attack { actor, target }
+    }
+
+    pub fn wait(actor: ActorId) -> Self {
+        Action::Wait { actor }
+    }
+
+    pub fn interact(actor: ActorId, at: impl AsPosition) -> Self {
+        Action::Interact {
+            actor,
+            at: at.into(),
+        }
+    }
+
+    pub fn actor(&self) -> ActorId {
+        match self {
+            Action::MoveActor { actor, .. }
+            | Action::Attack { actor, .. }
+            | Action::Wait { actor }
+            | Action::Interact { actor, .. } => *actor,
+        }
+    }
+
+    // Energy spent by the actor once the action is performed
+    pub fn cost(&self) -> i32 {
+        match self {
+            Action::MoveActor { .. }
+            | Action::Attack { .. }
+            | Action::Wait { .. }
+            | Action::Interact { .. } => TURN_ENERGY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct ActionOutcome {
+    // Everything the action changed, in the order it happened
+    pub events: Vec<Event>,
+}
+
+impl ActionOutcome {
+    pub fn from_events(events: impl IntoIterator<Item = Event>) -> Self {
+        Self {
+            events: events.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum ActionError {
+    #[error("the actor is no longer in the world")]
+    InvalidActor,
+    #[error("there is no tile at ({}, {})", .0.x, .0.y)]
+    NoTile(Position),
+    #[error("there is no actor at ({}, {})", .0.x, .0.y)]
+    NoActor(Position),
+    #[error("the tile at ({}, {}) can't be walked on", .0.x, .0.y)]
+    NotWalkable(Position),
+    #[error("the tile at ({}, {}) is already occupied", .0.x, .0.y)]
+    Occupied(Position),
+    #[error("the target is no longer in the world")]
+    InvalidTarget,
+    #[error("the target at ({}, {}) is out of reach", .0.x, .0.y)]
+    OutOfReach(Position),
+    #[error("the target isn't hostile")]
+    NotHostile,
+    #[error("there is nothing to interact with at ({}, {})", .0.x, .0.y)]
+    NoFeature(Position),
+    #[error("the feature at ({}, {}) is locked", .0.x, .0.y)]
+    Locked(Position),
+    #[error("there is no level {0}")]
+    NoLevel(LevelId),
+    #[error("the actor is on level {0}, away from the player")]
+    ElsewhereActor(LevelId),
+    #[error("the stairs at ({}, {}) don't lead anywhere", .0.x, .0.y)]
+    NoDestination(Position),
+    #[error("it isn't the actor's turn")]
+    NotReady,
 }
+
+pub type ActionResult = Result<ActionOutcome, ActionError>;
@@ -1,14 +1,31 @@
-use std::alloc::{alloc, dealloc, Layout};
-use std::borrow::Borrow;
-use std::{cell::Cell, ptr::NonNull, rc::Rc};
+use std::rc::Rc;
 
-use crate::Position;
+use crate::{ArenaIndex, LevelId, Position};
+
+// Stays valid for as long as the actor is in the world, never reused afterwards
+pub type ActorId = ArenaIndex;
+
+// Energy an actor with an unmodified speed gains every scheduler tick
+pub const DEFAULT_SPEED: i32 = 10;
+pub const DEFAULT_HEALTH: i32 = 10;
+pub const DEFAULT_DAMAGE: i32 = 1;
+// How far an actor sees, in tiles
+pub const DEFAULT_SIGHT_RADIUS: f32 = 8.;
+
+// Actors of different factions are hostile to each other
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Faction(pub u16);
 
 #[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub struct ActorTemplate {
     display_name: String,
     resource_name: String,
+    speed: i32,
+    max_health: i32,
+    damage: i32,
+    faction: Faction,
+    sight_radius: f32,
 }
 
 impl ActorTemplate {
@@ -16,206 +33,136 @@ impl ActorTemplate {
         Self {
             display_name: name.to_string(),
             resource_name: resource_name.to_string(),
+            speed: DEFAULT_SPEED,
+            max_health: DEFAULT_HEALTH,
+            damage: DEFAULT_DAMAGE,
+            faction: Faction::default(),
+            sight_radius: DEFAULT_SIGHT_RADIUS,
         }
     }
 
-    pub fn resource_name(&self) -> &str {
-        &self.resource_name
+    pub fn with_speed(mut self, speed: i32) -> Self {
+        self.speed = speed;
+        self
     }
 
-    pub fn name(&self) -> &str {
-        &self.display_name
+    pub fn with_health(mut self, max_health: i32) -> Self {
+        self.max_health = max_health;
+        self
     }
-}
 
-#[derive(Debug, Clone, PartialEq)]
-#[non_exhaustive]
-pub struct Actor {
-    template: Rc<ActorTemplate>,
-}
-
-impl Actor {
-    pub fn template(&self) -> &ActorTemplate {
-        &self.template
+    pub fn with_damage(mut self, damage: i32) -> Self {
+        self.damage = damage;
+        self
     }
 
-    pub fn from_template(template: Rc<ActorTemplate>) -> Actor {
-        Self { template }
+    pub fn with_faction(mut self, faction: Faction) -> Self {
+        self.faction = faction;
+        self
     }
-}
 
-impl From<Rc<ActorTemplate>> for Actor {
-    fn from(template: Rc<ActorTemplate>) -> Self {
-        Self { template }
+    pub fn with_sight_radius(mut self, sight_radius: f32) -> Self {
+        self.sight_radius = sight_radius;
+        self
     }
-}
 
-pub struct ValidActorData {
-    pub cached_position: Position,
-}
-
-pub struct ActorData {
-    // Data that is always valid when referencing the actor
-    pub(crate) weak_keep_alive: (Cell<usize>, Actor),
-
-    // Data that is only valid while the actor exists in the world
-    pub(crate) valid_actor_data: Option<ValidActorData>,
-}
-
-impl ActorData {
-    pub fn layout() -> Layout {
-        Layout::new::<Self>()
+    pub fn speed(&self) -> i32 {
+        self.speed
     }
 
-    pub fn actor(&self) -> &Actor {
-        &self.weak_keep_alive.1
+    pub fn max_health(&self) -> i32 {
+        self.max_health
     }
 
-    pub fn is_valid(&self) -> bool {
-        self.valid_actor_data.is_some()
+    pub fn damage(&self) -> i32 {
+        self.damage
     }
 
-    pub fn try_valid_data(&self) -> Option<&ValidActorData> {
-        self.valid_actor_data.as_ref()
+    pub fn faction(&self) -> Faction {
+        self.faction
     }
 
-    pub unsafe fn from_ptr<'a>(ptr: NonNull<ActorData>) -> &'a mut ActorData {
-        unsafe { ptr.as_ptr().as_mut().unwrap() }
+    pub fn sight_radius(&self) -> f32 {
+        self.sight_radius
     }
-}
 
-// An owning handle to an existing actor
-#[derive(PartialEq, Eq)]
-pub struct ActorHandle {
-    heap: NonNull<ActorData>,
-}
-
-impl std::fmt::Debug for ActorHandle {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ActorHandle")
-            .field("actor", self.get_data().actor())
-            .finish()
+    pub fn is_hostile_to(&self, other: &ActorTemplate) -> bool {
+        self.faction != other.faction
     }
-}
 
-impl ActorHandle {
-    pub fn from_actor(actor: Actor, cached_position: Position) -> Self {
-        let heap = unsafe {
-            let heap = alloc(ActorData::layout()) as *mut ActorData;
-            heap.as_uninit_mut().unwrap().write(ActorData {
-                weak_keep_alive: (Cell::new(1), actor),
-                valid_actor_data: Some(ValidActorData { cached_position }),
-            });
-            heap
-        };
-
-        Self {
-            heap: NonNull::new(heap).unwrap(),
-        }
+    pub fn resource_name(&self) -> &str {
+        &self.resource_name
     }
 
-    pub fn as_weak(&self) -> ActorReference {
-        ActorReference::from_heap(self.heap)
+    pub fn name(&self) -> &str {
+        &self.display_name
     }
+}
 
-    pub fn get_data(&self) -> &ActorData {
-        unsafe { ActorData::from_ptr(self.heap) }
-    }
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Actor {
+    template: Rc<ActorTemplate>,
+}
 
-    pub fn get_data_mut(&mut self) -> &mut ActorData {
-        unsafe { ActorData::from_ptr(self.heap) }
+impl Actor {
+    pub fn template(&self) -> &ActorTemplate {
+        &self.template
     }
 
-    pub fn valid_data(&self) -> &ValidActorData {
-        assert!(self.get_data().is_valid());
-        self.get_data().valid_actor_data.as_ref().unwrap()
+    pub fn template_handle(&self) -> &Rc<ActorTemplate> {
+        &self.template
     }
-}
 
-impl Drop for ActorHandle {
-    fn drop(&mut self) {
-        let heap = unsafe { self.heap.as_mut() };
-
-        // Invalidate the actor data
-        heap.valid_actor_data = None;
-        let (refs, _) = &heap.weak_keep_alive;
-        refs.set(refs.get() - 1);
-        if refs.get() == 0 {
-            unsafe { dealloc(self.heap.as_ptr() as *mut u8, ActorData::layout()) };
-        }
+    pub fn from_template(template: Rc<ActorTemplate>) -> Actor {
+        Self { template }
     }
 }
 
-impl Borrow<Actor> for ActorHandle {
-    fn borrow(&self) -> &Actor {
-        unsafe { &self.heap.as_ref().weak_keep_alive.1 }
+impl From<Rc<ActorTemplate>> for Actor {
+    fn from(template: Rc<ActorTemplate>) -> Self {
+        Self { template }
     }
 }
 
-#[derive(Hash, PartialEq, Eq)]
-pub struct ActorReference {
-    heap: NonNull<ActorData>,
-}
-
-impl std::fmt::Debug for ActorReference {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ActorReference")
-            .field("heap", &self.as_actor_ref())
-            .finish()
-    }
+// Everything the world knows about an actor it owns
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ActorData {
+    pub actor: Actor,
+    // Only ever changed through the registry so the index stays in sync
+    pub(crate) level: LevelId,
+    pub(crate) position: Position,
+    // Accumulated by the scheduler, spent by performing actions
+    pub energy: i32,
+    // The actor dies once it drops to zero
+    pub health: i32,
 }
 
-impl ActorReference {
-    pub(crate) fn from_heap(heap: NonNull<ActorData>) -> Self {
-        let (refs, _) = unsafe { &ActorData::from_ptr(heap).weak_keep_alive };
-        refs.set(refs.get() + 1);
-        Self { heap }
-    }
-
-    pub fn as_actor_ref(&self) -> &Actor {
-        &unsafe { self.heap.as_ptr().as_mut() }
-            .unwrap()
-            .weak_keep_alive
-            .1
-    }
-
-    pub fn try_as_valid(&self) -> Option<(&Actor, &ValidActorData)> {
-        unsafe {
-            match ActorData::from_ptr(self.heap) {
-                ActorData {
-                    weak_keep_alive: (_, actor),
-                    valid_actor_data: Some(va),
-                } => Some((actor, va)),
-                _ => None,
-            }
+impl ActorData {
+    pub fn new(actor: Actor, level: LevelId, position: Position) -> Self {
+        Self {
+            health: actor.template().max_health(),
+            energy: 0,
+            level,
+            position,
+            actor,
         }
     }
 
-    pub fn get_data(&self) -> &ActorData {
-        unsafe { ActorData::from_ptr(self.heap) }
+    pub fn actor(&self) -> &Actor {
+        &self.actor
     }
-}
 
-impl AsRef<Actor> for ActorReference {
-    fn as_ref(&self) -> &Actor {
-        unsafe { &self.heap.as_ref().weak_keep_alive.1 }
+    pub fn level(&self) -> LevelId {
+        self.level
     }
-}
 
-impl Clone for ActorReference {
-    fn clone(&self) -> Self {
-        let (refs, _) = unsafe { &ActorData::from_ptr(self.heap).weak_keep_alive };
-        refs.set(refs.get() + 1);
-        Self { heap: self.heap }
+    pub fn position(&self) -> Position {
+        self.position
     }
-}
 
-impl Drop for ActorReference {
-    fn drop(&mut self) {
-        let (refs, _) = unsafe { &ActorData::from_ptr(self.heap).weak_keep_alive };
-        refs.set(refs.get() - 1);
-        if refs.get() == 0 {
-            unsafe { dealloc(self.heap.as_ptr() as *mut u8, ActorData::layout()) };
-        }
+    pub fn template(&self) -> &ActorTemplate {
+        self.actor.template()
     }
 }
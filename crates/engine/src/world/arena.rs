@@ -0,0 +1,132 @@
+// A key into an `Arena`, stale once the slot it points to gets reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArenaIndex {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            len: 0,
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> ArenaIndex {
+        self.len += 1;
+
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.generation += 1;
+                slot.value = Some(value);
+                ArenaIndex {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                let index = self.slots.len() as u32;
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                ArenaIndex {
+                    index,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, idx: ArenaIndex) -> Option<T> {
+        let slot = self.slots.get_mut(idx.index as usize)?;
+        if slot.generation != idx.generation {
+            return None;
+        }
+
+        let value = slot.value.take()?;
+        self.free.push(idx.index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn contains(&self, idx: ArenaIndex) -> bool {
+        self.get(idx).is_some()
+    }
+
+    pub fn get(&self, idx: ArenaIndex) -> Option<&T> {
+        self.slots
+            .get(idx.index as usize)
+            .filter(|slot| slot.generation == idx.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, idx: ArenaIndex) -> Option<&mut T> {
+        self.slots
+            .get_mut(idx.index as usize)
+            .filter(|slot| slot.generation == idx.generation)
+            .and_then(|slot| slot.value.as_mut())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ArenaIndex, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value.as_ref().map(|value| {
+                (
+                    ArenaIndex {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ArenaIndex, &mut T)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let generation = slot.generation;
+                slot.value.as_mut().map(|value| {
+                    (
+                        ArenaIndex {
+                            index: index as u32,
+                            generation,
+                        },
+                        value,
+                    )
+                })
+            })
+    }
+}
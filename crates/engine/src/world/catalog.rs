@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, fmt::Display, rc::Rc};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{ActorTemplate, MaterialHandle};
+
+// Stays the same between runs, unlike the handles which are just pointers
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MaterialId(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TemplateId(pub String);
+
+impl Display for MaterialId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Display for TemplateId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Every material and actor template the game knows about, by their stable ids
+#[derive(Debug, Default, Clone)]
+pub struct Catalog {
+    materials: BTreeMap<MaterialId, MaterialHandle>,
+    templates: BTreeMap<TemplateId, Rc<ActorTemplate>>,
+    // Reverse lookups by the address of the handle
+    material_ids: HashMap<usize, MaterialId>,
+    template_ids: HashMap<usize, TemplateId>,
+}
+
+impl Catalog {
+    // Returns the material that had the id before
+    pub fn insert_material(
+        &mut self,
+        id: MaterialId,
+        material: MaterialHandle,
+    ) -> Option<MaterialHandle> {
+        self.material_ids
+            .insert(Rc::as_ptr(&material) as usize, id.clone());
+        let displaced = self.materials.insert(id, material)?;
+        self.material_ids.remove(&(Rc::as_ptr(&displaced) as usize));
+        Some(displaced)
+    }
+
+    // Returns the template that had the id before
+    pub fn insert_template(
+        &mut self,
+        id: TemplateId,
+        template: Rc<ActorTemplate>,
+    ) -> Option<Rc<ActorTemplate>> {
+        self.template_ids
+            .insert(Rc::as_ptr(&template) as usize, id.clone());
+        let displaced = self.templates.insert(id, template)?;
+        self.template_ids.remove(&(Rc::as_ptr(&displaced) as usize));
+        Some(displaced)
+    }
+
+    pub fn material(&self, id: &MaterialId) -> Option<&MaterialHandle> {
+        self.materials.get(id)
+    }
+
+    pub fn template(&self, id: &TemplateId) -> Option<&Rc<ActorTemplate>> {
+        self.templates.get(id)
+    }
+
+    // Only the exact handle that was registered has an id, not an equal copy of it
+    pub fn material_id(&self, material: &MaterialHandle) -> Option<&MaterialId> {
+        self.material_ids.get(&(Rc::as_ptr(material) as usize))
+    }
+
+    pub fn template_id(&self, template: &Rc<ActorTemplate>) -> Option<&TemplateId> {
+        self.template_ids.get(&(Rc::as_ptr(template) as usize))
+    }
+
+    pub fn materials(&self) -> impl Iterator<Item = (&MaterialId, &MaterialHandle)> {
+        self.materials.iter()
+    }
+
+    pub fn templates(&self) -> impl Iterator<Item = (&TemplateId, &Rc<ActorTemplate>)> {
+        self.templates.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Material, MaterialFlags};
+
+    fn floor() -> MaterialHandle {
+        Rc::new(Material::new(
+            "Floor",
+            "floor",
+            None::<String>,
+            MaterialFlags::FLOOR,
+        ))
+    }
+
+    #[test]
+    fn ids_belong_to_the_registered_handle() {
+        let mut catalog = Catalog::default();
+        let id = MaterialId("floor".to_string());
+        let floor = floor();
+        catalog.insert_material(id.clone(), floor.clone());
+
+        assert_eq!(catalog.material_id(&floor), Some(&id));
+        // Equal, but not the same handle
+        assert_eq!(catalog.material_id(&self::floor()), None);
+    }
+
+    #[test]
+    fn replaced_handles_lose_their_id() {
+        let mut catalog = Catalog::default();
+        let id = MaterialId("floor".to_string());
+        let (old, new) = (floor(), floor());
+        catalog.insert_material(id.clone(), old.clone());
+
+        let displaced = catalog.insert_material(id.clone(), new.clone());
+        assert!(displaced.map_or(false, |x| Rc::ptr_eq(&x, &old)));
+        assert_eq!(catalog.material_id(&old), None);
+        assert_eq!(catalog.material_id(&new), Some(&id));
+    }
+}
@@ -0,0 +1,99 @@
+use std::any::{Any, TypeId};
+
+use hashbrown::HashMap;
+
+use crate::ActorId;
+
+// Anything can be attached to an actor as long as it doesn't borrow
+pub trait Component = Any;
+
+trait ComponentColumn {
+    fn remove_actor(&mut self, actor: ActorId);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> ComponentColumn for HashMap<ActorId, T> {
+    fn remove_actor(&mut self, actor: ActorId) {
+        self.remove(&actor);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Components of every actor, stored in a column per component type
+#[derive(Default)]
+pub struct ComponentStore {
+    columns: HashMap<TypeId, Box<dyn ComponentColumn>>,
+}
+
+impl ComponentStore {
+    pub fn insert<T: Component>(&mut self, actor: ActorId, component: T) -> Option<T> {
+        self.columns
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::<HashMap<ActorId, T>>::default())
+            .as_any_mut()
+            .downcast_mut::<HashMap<ActorId, T>>()
+            .expect("Component column has the wrong type")
+            .insert(actor, component)
+    }
+
+    pub fn get<T: Component>(&self, actor: ActorId) -> Option<&T> {
+        self.column::<T>()?.get(&actor)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, actor: ActorId) -> Option<&mut T> {
+        self.column_mut::<T>()?.get_mut(&actor)
+    }
+
+    pub fn remove<T: Component>(&mut self, actor: ActorId) -> Option<T> {
+        self.column_mut::<T>()?.remove(&actor)
+    }
+
+    // Drops every component attached to the actor
+    pub fn remove_all(&mut self, actor: ActorId) {
+        for column in self.columns.values_mut() {
+            column.remove_actor(actor);
+        }
+    }
+
+    pub fn query<T: Component>(&self) -> impl Iterator<Item = (ActorId, &T)> {
+        self.column::<T>()
+            .into_iter()
+            .flat_map(|column| column.iter().map(|(id, x)| (*id, x)))
+    }
+
+    pub fn query_mut<T: Component>(&mut self) -> impl Iterator<Item = (ActorId, &mut T)> {
+        self.column_mut::<T>()
+            .into_iter()
+            .flat_map(|column| column.iter_mut().map(|(id, x)| (*id, x)))
+    }
+
+    fn column<T: Component>(&self) -> Option<&HashMap<ActorId, T>> {
+        self.columns
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<HashMap<ActorId, T>>()
+    }
+
+    fn column_mut<T: Component>(&mut self) -> Option<&mut HashMap<ActorId, T>> {
+        self.columns
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<HashMap<ActorId, T>>()
+    }
+}
+
+impl std::fmt::Debug for ComponentStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentStore")
+            .field("columns", &self.columns.len())
+            .finish()
+    }
+}
@@ -0,0 +1,300 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use hashbrown::HashMap;
+
+use crate::{AsPosition, Grid, PathOptions, Position, Tile};
+
+// Distance from every reachable tile to the closest goal,
+// actors get to a goal by always stepping onto a neighbour with a lower value
+#[derive(Debug, Clone)]
+pub struct DijkstraMap {
+    values: HashMap<Position, i32>,
+    // Goals and the value they start at, lower values attract more
+    goals: HashMap<Position, i32>,
+    options: PathOptions,
+}
+
+impl DijkstraMap {
+    // Occupied tiles are passable at `options.occupied_cost`, or for free if there is none,
+    // so the actors using the map don't cut it off for each other
+    pub fn new(
+        grid: &Grid,
+        goals: impl IntoIterator<Item = (Position, i32)>,
+        options: PathOptions,
+    ) -> Self {
+        let mut map = Self {
+            values: HashMap::default(),
+            goals: HashMap::from_iter(goals),
+            options,
+        };
+        map.rebuild(grid);
+        map
+    }
+
+    pub fn value(&self, position: impl AsPosition) -> Option<i32> {
+        self.values.get(&position.into()).copied()
+    }
+
+    pub fn goals(&self) -> impl Iterator<Item = (Position, i32)> + '_ {
+        self.goals.iter().map(|(pos, weight)| (*pos, *weight))
+    }
+
+    pub fn rebuild(&mut self, grid: &Grid) {
+        self.values.clear();
+        let seeds: Vec<_> = self
+            .goals
+            .iter()
+            .map(|(pos, weight)| (*weight, *pos))
+            .collect();
+        self.propagate(grid, seeds);
+    }
+
+    // Adding a goal only ever lowers values, so only the affected area is updated
+    pub fn add_goal(&mut self, grid: &Grid, position: impl AsPosition, weight: i32) {
+        let position = position.into();
+        if self.goals.contains_key(&position) {
+            // The old weight might have been lower, which needs invalidating
+            self.remove_goal(grid, position);
+        }
+
+        self.goals.insert(position, weight);
+        self.propagate(grid, [(weight, position)]);
+    }
+
+    // Invalidates everything that was reached through the goal and refills it from the rest
+    pub fn remove_goal(&mut self, grid: &Grid, position: impl AsPosition) {
+        let position = position.into();
+        if self.goals.remove(&position).is_none() {
+            return;
+        }
+
+        self.values.remove(&position);
+        let mut invalidated = vec![position];
+        let mut stack = vec![position];
+        while let Some(at) = stack.pop() {
+            for (neighbour, tile) in self.options.neighbours(grid, at) {
+                if self.values.contains_key(&neighbour) && !self.is_supported(grid, neighbour, tile)
+                {
+                    self.values.remove(&neighbour);
+                    invalidated.push(neighbour);
+                    stack.push(neighbour);
+                }
+            }
+        }
+
+        let seeds: Vec<_> = invalidated
+            .into_iter()
+            .filter_map(|at| {
+                let cost = grid.get_tile(at).and_then(|tile| self.cost(tile));
+                let through_neighbour = self
+                    .options
+                    .neighbours(grid, at)
+                    .filter_map(|(neighbour, _)| self.value(neighbour))
+                    .min()
+                    .zip(cost)
+                    .map(|(value, cost)| value + cost as i32);
+
+                through_neighbour
+                    .into_iter()
+                    .chain(self.goals.get(&at).copied())
+                    .min()
+                    .map(|value| (value, at))
+            })
+            .collect();
+        self.propagate(grid, seeds);
+    }
+
+    // Follows a goal to its new position, e.g. after the player moves
+    pub fn move_goal(&mut self, grid: &Grid, from: impl AsPosition, to: impl AsPosition) {
+        let from = from.into();
+        let weight = self.goals.get(&from).copied().unwrap_or(0);
+        self.remove_goal(grid, from);
+        self.add_goal(grid, to, weight);
+    }
+
+    // A map that leads away from the goals. Fleeing actors still prefer
+    // far away tiles over cornering themselves next to the goal.
+    // Values are scaled by `coefficient`, which should be below -1.
+    pub fn flee(&self, grid: &Grid, coefficient: f32) -> DijkstraMap {
+        let seeds: Vec<_> = self
+            .values
+            .iter()
+            .map(|(pos, value)| ((*value as f32 * coefficient).round() as i32, *pos))
+            .collect();
+
+        let mut map = Self {
+            values: HashMap::default(),
+            goals: seeds.iter().map(|(value, pos)| (*pos, *value)).collect(),
+            options: self.options.clone(),
+        };
+        map.propagate(grid, seeds);
+        map
+    }
+
+    // The neighbour to step onto to get closer to a goal, if there is a free one
+    pub fn downhill(&self, grid: &Grid, from: impl AsPosition) -> Option<Position> {
+        let from = from.into();
+        let current = self.value(from)?;
+
+        self.options
+            .neighbours(grid, from)
+            .filter(|(pos, tile)| {
+                let is_free = tile.is_traversable(self.options.traversal) && !tile.is_occupied();
+                is_free || self.goals.contains_key(pos)
+            })
+            .filter_map(|(pos, _)| self.value(pos).map(|value| (value, pos)))
+            .filter(|(value, _)| *value < current)
+            .min_by_key(|(value, _)| *value)
+            .map(|(_, pos)| pos)
+    }
+
+    fn cost(&self, tile: &Tile) -> Option<u32> {
+        let occupied_cost = match tile.is_occupied() {
+            true => self.options.occupied_cost.unwrap_or(0),
+            false => 0,
+        };
+
+        self.options
+            .terrain_cost(tile)
+            .map(|cost| cost + occupied_cost)
+    }
+
+    // Whether the tile is a goal or some neighbour still accounts for its value
+    fn is_supported(&self, grid: &Grid, at: Position, tile: &Tile) -> bool {
+        let Some(value) = self.value(at) else {
+            return false;
+        };
+
+        if self.goals.get(&at) == Some(&value) {
+            return true;
+        }
+
+        let Some(cost) = self.cost(tile) else {
+            return false;
+        };
+
+        self.options
+            .neighbours(grid, at)
+            .filter_map(|(neighbour, _)| self.value(neighbour))
+            .any(|neighbour| neighbour + cost as i32 == value)
+    }
+
+    fn propagate(&mut self, grid: &Grid, seeds: impl IntoIterator<Item = (i32, Position)>) {
+        let mut frontier: BinaryHeap<_> = seeds
+            .into_iter()
+            .map(|(value, pos)| Reverse((value, pos.x, pos.y)))
+            .collect();
+
+        while let Some(Reverse((value, x, y))) = frontier.pop() {
+            let at = Position::new(x, y);
+            if self.value(at).map_or(false, |current| current <= value) {
+                continue;
+            }
+            self.values.insert(at, value);
+
+            for (neighbour, tile) in self.options.neighbours(grid, at) {
+                let Some(cost) = self.cost(tile) else {
+                    continue;
+                };
+
+                let candidate = value + cost as i32;
+                if self
+                    .value(neighbour)
+                    .map_or(true, |current| candidate < current)
+                {
+                    frontier.push(Reverse((candidate, neighbour.x, neighbour.y)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Material, MaterialFlags};
+
+    // `~` is mud, which takes longer to cross
+    const MAP: &str = "\
+##########
+#....#...#
+#.~~.#.#.#
+#.~~...#.#
+#....###.#
+#.#......#
+##########";
+
+    fn grid() -> Grid {
+        let floor = Rc::new(Material::new(
+            "Floor",
+            "floor",
+            None::<String>,
+            MaterialFlags::FLOOR,
+        ));
+        let mud = Rc::new(
+            Material::new("Mud", "mud", None::<String>, MaterialFlags::FLOOR).with_movement_cost(3),
+        );
+        let wall = Rc::new(Material::new(
+            "Wall",
+            "wall",
+            None::<String>,
+            MaterialFlags::WALL,
+        ));
+
+        let lines: Vec<&str> = MAP.lines().rev().collect();
+        let mut grid = Grid::new(lines[0].len() as u16, lines.len() as u16);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, char) in line.chars().enumerate() {
+                let material = match char {
+                    '#' => &wall,
+                    '~' => &mud,
+                    _ => &floor,
+                };
+                grid.make_tile_at([x as i32, y as i32], material.clone());
+            }
+        }
+        grid
+    }
+
+    fn assert_rebuilt(grid: &Grid, map: &DijkstraMap) {
+        let rebuilt = DijkstraMap::new(grid, map.goals(), map.options.clone());
+        for position in grid.tiles().keys() {
+            assert_eq!(
+                map.value(*position),
+                rebuilt.value(*position),
+                "{position:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_updates_match_a_rebuild() {
+        let grid = grid();
+        let mut map = DijkstraMap::new(&grid, [(Position::new(1, 1), 0)], PathOptions::default());
+        assert_rebuilt(&grid, &map);
+
+        map.add_goal(&grid, [8, 5], 0);
+        assert_rebuilt(&grid, &map);
+
+        // Both a heavier and a lighter weight on an existing goal
+        map.add_goal(&grid, [8, 5], 4);
+        assert_rebuilt(&grid, &map);
+        map.add_goal(&grid, [8, 5], -2);
+        assert_rebuilt(&grid, &map);
+
+        map.move_goal(&grid, [1, 1], [3, 4]);
+        assert_rebuilt(&grid, &map);
+
+        map.move_goal(&grid, [3, 4], [6, 1]);
+        assert_rebuilt(&grid, &map);
+
+        map.remove_goal(&grid, [8, 5]);
+        assert_rebuilt(&grid, &map);
+
+        map.remove_goal(&grid, [6, 1]);
+        assert_rebuilt(&grid, &map);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{ActorId, Feature, LevelId, MaterialHandle, Observer, Position};
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    ActorMoved {
+        actor: ActorId,
+        from: Position,
+        to: Position,
+    },
+    ActorSpawned {
+        actor: ActorId,
+        at: Position,
+    },
+    ActorDespawned {
+        actor: ActorId,
+        at: Position,
+    },
+    ActorAttacked {
+        attacker: ActorId,
+        target: ActorId,
+        damage: i32,
+    },
+    ActorDied {
+        actor: ActorId,
+        at: Position,
+    },
+    ActorTravelled {
+        actor: ActorId,
+        from: LevelId,
+        to: LevelId,
+        at: Position,
+    },
+    // The player went to another level, which is the current one from now on
+    LevelChanged {
+        from: LevelId,
+        to: LevelId,
+    },
+    TileChanged {
+        position: Position,
+        // None if there was no tile before
+        from: Option<MaterialHandle>,
+        to: MaterialHandle,
+    },
+    FeatureChanged {
+        position: Position,
+        from: Option<Feature>,
+        to: Option<Feature>,
+    },
+    FeatureInteracted {
+        actor: ActorId,
+        position: Position,
+        feature: Feature,
+    },
+    TileDiscovered {
+        observer: Observer,
+        position: Position,
+    },
+}
+
+pub type EventCallback = Box<dyn FnMut(&Event)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+// Hands every published event to the subscribers
+// and keeps it around until it gets drained
+#[derive(Default)]
+pub struct EventBus {
+    queue: Vec<Event>,
+    subscribers: Vec<(SubscriptionId, EventCallback)>,
+    next_subscription: usize,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self, callback: impl FnMut(&Event) + 'static) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription);
+        self.next_subscription += 1;
+        self.subscribers.push((id, Box::new(callback)));
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers
+            .retain(|(subscription, _)| *subscription != id);
+        len != self.subscribers.len()
+    }
+
+    pub fn publish(&mut self, events: impl IntoIterator<Item = Event>) {
+        for event in events {
+            for (_, callback) in &mut self.subscribers {
+                callback(&event);
+            }
+            self.queue.push(event);
+        }
+    }
+
+    pub fn drain(&mut self) -> std::vec::Drain<'_, Event> {
+        self.queue.drain(..)
+    }
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("queue", &self.queue)
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
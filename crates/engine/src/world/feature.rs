@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum FeatureKind {
+    // Stops actors and sight unless open
+    Door,
+    // Flips between open and closed, what it is wired to is up to the game
+    Lever,
+    // Leads to another level
+    Stairs,
+    // Actors can't walk through it, but can see over it
+    Fountain,
+}
+
+impl FeatureKind {
+    pub const ALL: [FeatureKind; 4] = [
+        FeatureKind::Door,
+        FeatureKind::Lever,
+        FeatureKind::Stairs,
+        FeatureKind::Fountain,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeatureKind::Door => "door",
+            FeatureKind::Lever => "lever",
+            FeatureKind::Stairs => "stairs",
+            FeatureKind::Fountain => "fountain",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FeatureState {
+    Open,
+    #[default]
+    Closed,
+    // Closed and can't be opened by interacting with it
+    Locked,
+}
+
+impl FeatureState {
+    pub const ALL: [FeatureState; 3] = [
+        FeatureState::Open,
+        FeatureState::Closed,
+        FeatureState::Locked,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeatureState::Open => "open",
+            FeatureState::Closed => "closed",
+            FeatureState::Locked => "locked",
+        }
+    }
+}
+
+// Something on a tile that actors can interact with, on top of its material
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Feature {
+    pub kind: FeatureKind,
+    pub state: FeatureState,
+}
+
+impl Feature {
+    pub fn new(kind: FeatureKind, state: FeatureState) -> Self {
+        Self { kind, state }
+    }
+
+    pub fn door(state: FeatureState) -> Self {
+        Self::new(FeatureKind::Door, state)
+    }
+
+    pub fn lever() -> Self {
+        Self::new(FeatureKind::Lever, FeatureState::Closed)
+    }
+
+    pub fn stairs() -> Self {
+        Self::new(FeatureKind::Stairs, FeatureState::Open)
+    }
+
+    pub fn fountain() -> Self {
+        Self::new(FeatureKind::Fountain, FeatureState::Open)
+    }
+
+    // Every kind in every state, any of them can end up on a tile through vaults or saves
+    pub fn all() -> impl Iterator<Item = Feature> {
+        FeatureKind::ALL.into_iter().flat_map(|kind| {
+            FeatureState::ALL
+                .into_iter()
+                .map(move |state| Self::new(kind, state))
+        })
+    }
+
+    pub fn with_state(mut self, state: FeatureState) -> Self {
+        self.state = state;
+        self
+    }
+
+    // Sprite of the feature, e.g. `feature.door.open`
+    pub fn resource_name(&self) -> String {
+        format!("feature.{}.{}", self.kind.name(), self.state.name())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state == FeatureState::Open
+    }
+
+    pub fn blocks_movement(&self) -> bool {
+        match self.kind {
+            FeatureKind::Door => !self.is_open(),
+            FeatureKind::Fountain => true,
+            FeatureKind::Lever | FeatureKind::Stairs => false,
+        }
+    }
+
+    pub fn blocks_projectiles(&self) -> bool {
+        match self.kind {
+            FeatureKind::Door => !self.is_open(),
+            FeatureKind::Lever | FeatureKind::Stairs | FeatureKind::Fountain => false,
+        }
+    }
+
+    pub fn blocks_sight(&self) -> bool {
+        match self.kind {
+            FeatureKind::Door => !self.is_open(),
+            FeatureKind::Lever | FeatureKind::Stairs | FeatureKind::Fountain => false,
+        }
+    }
+
+    // The feature after someone interacts with it, None if it doesn't change
+    pub fn interacted(&self) -> Option<Feature> {
+        let state = match (self.kind, self.state) {
+            (_, FeatureState::Locked) => return None,
+            (FeatureKind::Door | FeatureKind::Lever, FeatureState::Open) => FeatureState::Closed,
+            (FeatureKind::Door | FeatureKind::Lever, FeatureState::Closed) => FeatureState::Open,
+            (FeatureKind::Stairs | FeatureKind::Fountain, _) => return None,
+        };
+
+        Some(self.with_state(state))
+    }
+}
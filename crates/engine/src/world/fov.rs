@@ -0,0 +1,254 @@
+use hashbrown::HashSet;
+
+use crate::{AsPosition, Grid, Position};
+
+// Slope of a line through the origin as a fraction, `den` is always positive
+#[derive(Debug, Clone, Copy)]
+struct Slope {
+    num: i64,
+    den: i64,
+}
+
+impl Slope {
+    fn new(num: i64, den: i64) -> Self {
+        Self { num, den }
+    }
+
+    // Slope of the edge of the tile facing the start of the row
+    fn of_tile(depth: i64, column: i64) -> Self {
+        Self::new(2 * column - 1, 2 * depth)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    depth: i64,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    fn columns(&self) -> std::ops::RangeInclusive<i64> {
+        // Rounding ties up at the start and down at the end
+        let min = (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den);
+        let max = -(-(2 * self.depth * self.end.num - self.end.den)).div_euclid(2 * self.end.den);
+        min..=max
+    }
+
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+
+    // Whether the center of the tile is within the row, which keeps the field of view symmetric
+    fn is_symmetric(&self, column: i64) -> bool {
+        column * self.start.den >= self.depth * self.start.num
+            && column * self.end.den <= self.depth * self.end.num
+    }
+}
+
+// Maps a (depth, column) pair in a quadrant onto the grid
+#[derive(Debug, Clone, Copy)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Quadrant {
+    fn transform(&self, origin: Position, depth: i64, column: i64) -> Position {
+        let (depth, column) = (depth as i32, column as i32);
+        match self {
+            Quadrant::North => origin + Position::new(column, depth),
+            Quadrant::South => origin + Position::new(column, -depth),
+            Quadrant::East => origin + Position::new(depth, column),
+            Quadrant::West => origin + Position::new(-depth, column),
+        }
+    }
+}
+
+fn is_within(radius: Option<f32>, offset: Position) -> bool {
+    radius.map_or(true, |radius| {
+        (offset.x * offset.x + offset.y * offset.y) as f32 <= radius * radius
+    })
+}
+
+// Symmetric shadowcasting, as described by Albert Ford.
+// If `a` can see `b` then `b` can see `a`, every visible tile is passed to `mark_visible`.
+// `max_depth` stops the scan early, tiles further than `radius` are never visible.
+pub fn shadowcast(
+    origin: impl AsPosition,
+    radius: Option<f32>,
+    max_depth: Option<u32>,
+    is_blocking: impl Fn(Position) -> bool,
+    mut mark_visible: impl FnMut(Position),
+) {
+    let origin = origin.into();
+    mark_visible(origin);
+
+    let max_depth = match (radius, max_depth) {
+        (Some(radius), Some(depth)) => (radius.floor() as i64).min(depth as i64),
+        (Some(radius), None) => radius.floor() as i64,
+        (None, Some(depth)) => depth as i64,
+        (None, None) => i64::MAX,
+    };
+
+    for quadrant in [
+        Quadrant::North,
+        Quadrant::East,
+        Quadrant::South,
+        Quadrant::West,
+    ] {
+        let mut rows = vec![Row {
+            depth: 1,
+            start: Slope::new(-1, 1),
+            end: Slope::new(1, 1),
+        }];
+
+        while let Some(mut row) = rows.pop() {
+            if row.depth > max_depth {
+                continue;
+            }
+
+            let mut previous_is_wall = None;
+            for column in row.columns() {
+                let position = quadrant.transform(origin, row.depth, column);
+                let is_wall = is_blocking(position);
+
+                if (is_wall || row.is_symmetric(column)) && is_within(radius, position - origin) {
+                    mark_visible(position);
+                }
+
+                match (previous_is_wall, is_wall) {
+                    (Some(true), false) => row.start = Slope::of_tile(row.depth, column),
+                    (Some(false), true) => {
+                        let mut next = row.next();
+                        next.end = Slope::of_tile(row.depth, column);
+                        rows.push(next);
+                    }
+                    _ => {}
+                }
+
+                previous_is_wall = Some(is_wall);
+            }
+
+            if previous_is_wall == Some(false) {
+                rows.push(row.next());
+            }
+        }
+    }
+}
+
+impl Grid {
+    // Every tile visible from the origin, tiles that don't exist block the view
+    pub fn field_of_view(&self, origin: impl AsPosition, radius: Option<f32>) -> HashSet<Position> {
+        let mut visible = HashSet::default();
+        shadowcast(
+            origin,
+            radius,
+            None,
+            |pos| {
+                self.get_tile(pos)
+                    .map_or(true, |tile| tile.is_sight_blocker())
+            },
+            |pos| {
+                visible.insert(pos);
+            },
+        );
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Material, MaterialFlags};
+
+    // Pillars and a broken wall, so there are plenty of shadows to get wrong
+    const MAP: &str = "\
+############
+#..........#
+#.#..#.....#
+#....#..#..#
+#..........#
+###.####.###
+#.....#....#
+#.#........#
+#....#..##.#
+############";
+
+    fn grid() -> Grid {
+        let floor = Rc::new(Material::new(
+            "Floor",
+            "floor",
+            None::<String>,
+            MaterialFlags::FLOOR,
+        ));
+        let wall = Rc::new(Material::new(
+            "Wall",
+            "wall",
+            None::<String>,
+            MaterialFlags::WALL,
+        ));
+
+        let lines: Vec<&str> = MAP.lines().rev().collect();
+        let mut grid = Grid::new(lines[0].len() as u16, lines.len() as u16);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, char) in line.chars().enumerate() {
+                let material = if char == '#' { &wall } else { &floor };
+                grid.make_tile_at([x as i32, y as i32], material.clone());
+            }
+        }
+        grid
+    }
+
+    fn floor(grid: &Grid) -> Vec<Position> {
+        grid.tiles()
+            .values()
+            .filter(|x| !x.is_sight_blocker())
+            .map(|x| x.position)
+            .collect()
+    }
+
+    #[test]
+    fn field_of_view_is_symmetric() {
+        let grid = grid();
+        let floor = floor(&grid);
+
+        for radius in [None, Some(4.5)] {
+            for a in &floor {
+                let seen = grid.field_of_view(*a, radius);
+                for b in &floor {
+                    let seen_back = grid.field_of_view(*b, radius).contains(a);
+                    assert_eq!(seen.contains(b), seen_back, "{a:?} and {b:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn los_check_agrees_with_field_of_view() {
+        let grid = grid();
+        let radius = 6.;
+
+        for from in floor(&grid) {
+            let seen = grid.field_of_view(from, Some(radius));
+            for to in grid.tiles().keys() {
+                let offset = to - from;
+                if (offset.x * offset.x + offset.y * offset.y) as f32 > radius * radius {
+                    continue;
+                }
+                assert_eq!(
+                    grid.los_check(from, *to, Some(radius)),
+                    seen.contains(to),
+                    "{from:?} to {to:?}"
+                );
+            }
+        }
+    }
+}
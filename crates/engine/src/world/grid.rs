@@ -4,17 +4,21 @@ use hashbrown::HashMap;
 use nalgebra_glm::{vec2, Vec2};
 use puffin_egui::puffin::profile_function;
 
-use crate::{min_max_aabb_from_rect, pos_to_vec2, vec2_to_pos};
-use crate::{
-    Actor, ActorHandle, ActorReference, AsPosition, MaterialFlags, MaterialHandle, Position,
-};
+use crate::{min_max_aabb_from_rect, pos_to_vec2, shadowcast, vec2_to_pos, Feature, LightSource};
+use crate::{ActionError, ActorId, AsPosition, Event, MaterialFlags, MaterialHandle, Position};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 #[non_exhaustive]
 pub struct Grid {
     pub size: Position,
-    pub tiles: hashbrown::HashMap<Position, Tile>,
-    pub discovered_tiles: RefCell<hashbrown::HashSet<Position>>,
+    // Only changed through the grid, so the flags revision can't miss a change
+    pub(crate) tiles: hashbrown::HashMap<Position, Tile>,
+    // Changes that haven't been picked up by the world yet
+    pub(crate) events: RefCell<Vec<Event>>,
+    // Lights that stay in place no matter what is on the tile
+    pub(crate) lights: HashMap<Position, LightSource>,
+    // Bumped whenever a tile appears or its material flags change
+    flags_revision: u64,
 }
 
 impl Grid {
@@ -25,19 +29,32 @@ impl Grid {
         Self {
             size: [width as i32, height as i32].into(),
             tiles: grid,
-            discovered_tiles: Default::default(),
+            events: Default::default(),
+            lights: HashMap::default(),
+            flags_revision: 0,
         }
     }
 
-    pub fn mark_visible(&self, position: impl AsPosition) {
-        self.discovered_tiles.borrow_mut().insert(position.into());
+    pub fn take_events(&self) -> Vec<Event> {
+        self.events.take()
     }
 
-    pub fn is_visible(&self, position: impl AsPosition) -> bool {
-        self.discovered_tiles.borrow().contains(&position.into())
+    pub(crate) fn record(&self, event: Event) {
+        self.events.borrow_mut().push(event);
     }
 
-    pub fn get_tile_mut(&mut self, position: impl AsPosition) -> Option<&mut Tile> {
+    // Anything computed from the tile flags is outdated once this changes
+    pub fn flags_revision(&self) -> u64 {
+        self.flags_revision
+    }
+
+    pub fn tiles(&self) -> &HashMap<Position, Tile> {
+        &self.tiles
+    }
+
+    // Whatever is done to the tile might change its flags, so cached results are dropped
+    pub(crate) fn get_tile_mut(&mut self, position: impl AsPosition) -> Option<&mut Tile> {
+        self.flags_revision += 1;
         self.tiles.get_mut(&position.into())
     }
 
@@ -45,20 +62,39 @@ impl Grid {
         self.tiles.get(&position.into())
     }
 
+    // Only changes the material, whoever stands on the tile and its feature stay there.
+    // Features are taken away with `set_feature`, actors through the world.
     pub fn make_tile_at(
         &mut self,
         position: impl AsPosition,
         material: MaterialHandle,
     ) -> (Option<Tile>, &Tile) {
         let pos = position.into();
+        let (occupier, feature) = self
+            .tiles
+            .get(&pos)
+            .map_or((None, None), |x| (x.occupier, x.feature));
         let displaced = self.tiles.insert(
             pos,
             Tile {
                 position: pos,
-                material,
-                occupier: None,
+                material: material.clone(),
+                occupier,
+                feature,
             },
         );
+        if displaced
+            .as_ref()
+            .map_or(true, |x| x.material.flags != material.flags)
+        {
+            self.flags_revision += 1;
+        }
+
+        self.record(Event::TileChanged {
+            position: pos,
+            from: displaced.as_ref().map(|x| x.material.clone()),
+            to: material,
+        });
         (
             displaced,
             self.tiles
@@ -67,6 +103,33 @@ impl Grid {
         )
     }
 
+    // Puts the feature on the tile or takes it away, returns the one that was there before
+    pub fn set_feature(
+        &mut self,
+        position: impl AsPosition,
+        feature: Option<Feature>,
+    ) -> Result<Option<Feature>, ActionError> {
+        let position = position.into();
+        let tile = self
+            .tiles
+            .get_mut(&position)
+            .ok_or(ActionError::NoTile(position))?;
+
+        let was_blocking = (tile.is_passable(), tile.is_sight_blocker());
+        let displaced = std::mem::replace(&mut tile.feature, feature);
+        if was_blocking != (tile.is_passable(), tile.is_sight_blocker()) {
+            self.flags_revision += 1;
+        }
+
+        self.record(Event::FeatureChanged {
+            position,
+            from: displaced,
+            to: feature,
+        });
+        Ok(displaced)
+    }
+
+    // Agrees with `field_of_view`, but only scans as far as it needs to
     pub fn los_check(
         &self,
         from: impl AsPosition,
@@ -74,25 +137,21 @@ impl Grid {
         max_distance: Option<f32>,
     ) -> bool {
         let (from, to): (Position, Position) = (from.into(), to.into());
+        let offset = to - from;
+        let depth = offset.x.unsigned_abs().max(offset.y.unsigned_abs());
 
-        if from == to {
-            return true;
-        }
-
-        let direction = pos_to_vec2(to - from).normalize();
-
-        let raycast = self.ray_cast(from, direction, max_distance);
-        for tile in raycast {
-            if tile.position == to {
-                return true;
-            }
-
-            if tile.is_sight_blocker() {
-                return false;
-            }
-        }
-
-        false
+        let mut is_visible = false;
+        shadowcast(
+            from,
+            max_distance,
+            Some(depth),
+            |pos| {
+                self.get_tile(pos)
+                    .map_or(true, |tile| tile.is_sight_blocker())
+            },
+            |pos| is_visible |= pos == to,
+        );
+        is_visible
     }
 
     pub fn ray_cast(
@@ -182,54 +241,91 @@ impl Grid {
         }
     }
 
-    pub fn move_actor(
+    pub(crate) fn move_actor(
         &mut self,
         from: impl AsPosition,
         to: impl AsPosition,
-    ) -> Option<(Option<ActorReference>, ActorReference)> {
-        let mut actor = self.get_tile_mut(from).and_then(|x| x.occupier.take())?;
-        let to = to.into();
-
-        let destination = self.get_tile_mut(to).map(|x| &mut x.occupier)?;
-        let mover = actor.as_weak();
-
-        assert!(actor.get_data().is_valid());
-        actor
-            .get_data_mut()
-            .valid_actor_data
-            .as_mut()?
-            .cached_position = to;
-
-        let moved = destination
-            .replace(actor)
-            .as_ref()
-            .map(ActorHandle::as_weak);
+    ) -> Result<ActorId, ActionError> {
+        let (from, to) = (from.into(), to.into());
 
-        Some((moved, mover))
+        match self.get_tile(to) {
+            None => return Err(ActionError::NoTile(to)),
+            Some(tile) if tile.is_occupied() => return Err(ActionError::Occupied(to)),
+            Some(_) => {}
+        }
+
+        let actor = self
+            .tiles
+            .get_mut(&from)
+            .ok_or(ActionError::NoTile(from))?
+            .occupier
+            .take()
+            .ok_or(ActionError::NoActor(from))?;
+
+        self.tiles
+            .get_mut(&to)
+            .expect("Destination tile was checked to exist")
+            .occupier = Some(actor);
+
+        self.record(Event::ActorMoved { actor, from, to });
+
+        Ok(actor)
     }
 
-    pub fn put_actor(&mut self, position: impl AsPosition, actor: Actor) -> Option<ActorReference> {
+    pub(crate) fn put_actor(
+        &mut self,
+        position: impl AsPosition,
+        actor: ActorId,
+    ) -> Result<(), ActionError> {
         let position = position.into();
 
-        match self.get_tile_mut(position) {
-            Some(tile) => {
-                let handle = ActorHandle::from_actor(actor, position);
-                let weak = handle.as_weak();
-                tile.occupier.replace(handle);
-                Some(weak)
-            }
-
-            None => None,
+        let tile = self
+            .tiles
+            .get_mut(&position)
+            .ok_or(ActionError::NoTile(position))?;
+        if tile.is_occupied() {
+            return Err(ActionError::Occupied(position));
         }
+
+        tile.occupier = Some(actor);
+        self.record(Event::ActorSpawned {
+            actor,
+            at: position,
+        });
+
+        Ok(())
+    }
+
+    pub(crate) fn remove_actor(
+        &mut self,
+        position: impl AsPosition,
+    ) -> Result<ActorId, ActionError> {
+        let position = position.into();
+
+        let actor = self
+            .tiles
+            .get_mut(&position)
+            .ok_or(ActionError::NoTile(position))?
+            .occupier
+            .take()
+            .ok_or(ActionError::NoActor(position))?;
+
+        self.record(Event::ActorDespawned {
+            actor,
+            at: position,
+        });
+
+        Ok(actor)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
 pub struct Tile {
     pub position: Position,
     pub material: MaterialHandle,
-    pub occupier: Option<ActorHandle>,
+    pub occupier: Option<ActorId>,
+    pub feature: Option<Feature>,
 }
 
 impl Tile {
@@ -238,11 +334,26 @@ impl Tile {
     }
 
     pub fn is_sight_blocker(&self) -> bool {
-        self.material.flags == MaterialFlags::SIGHTBLOCKER
+        !self.material.is_transparent() || self.feature.map_or(false, |x| x.blocks_sight())
+    }
+
+    pub fn blocks_projectiles(&self) -> bool {
+        self.material.blocks_projectiles() || self.feature.map_or(false, |x| x.blocks_projectiles())
+    }
+
+    // Whether the tile lets walking actors through, regardless of who stands on it
+    pub fn is_passable(&self) -> bool {
+        self.is_traversable(MaterialFlags::WALKABLE)
+    }
+
+    // Whether an actor moving in any of the given ways could get through the tile
+    pub fn is_traversable(&self, traversal: MaterialFlags) -> bool {
+        self.material.flags.intersects(traversal)
+            && !self.feature.map_or(false, |x| x.blocks_movement())
     }
 
     pub fn is_walkable(&self) -> bool {
-        self.material.flags == MaterialFlags::PASSTHROUGH && !self.is_occupied()
+        self.is_passable() && !self.is_occupied()
     }
 
     pub fn world_position(&self) -> Vec2 {
@@ -0,0 +1,153 @@
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+    Actor, ActorId, ActorRegistry, Faction, Feature, Grid, Lighting, MaterialHandle, Position,
+};
+
+// Whoever the knowledge belongs to, a faction shares what all of its members see
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Observer {
+    Actor(ActorId),
+    Faction(Faction),
+}
+
+impl Observer {
+    pub fn includes(&self, actor: ActorId, registry: &ActorRegistry) -> bool {
+        match *self {
+            Observer::Actor(id) => id == actor,
+            Observer::Faction(faction) => registry
+                .get(actor)
+                .map_or(false, |x| x.template().faction() == faction),
+        }
+    }
+}
+
+// The tile as it was the last time the observer saw it
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct RememberedTile {
+    pub material: MaterialHandle,
+    pub actor: Option<Actor>,
+    pub feature: Option<Feature>,
+}
+
+// Somewhere the observer looks from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewpoint {
+    pub origin: Position,
+    pub radius: f32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Knowledge {
+    visible: HashSet<Position>,
+    remembered: HashMap<Position, RememberedTile>,
+    // What the visible tiles were computed from, nothing is recomputed until either changes
+    viewpoints: Vec<Viewpoint>,
+    flags_revision: Option<u64>,
+    lighting_revision: Option<u64>,
+}
+
+impl Knowledge {
+    pub fn is_visible(&self, position: Position) -> bool {
+        self.visible.contains(&position)
+    }
+
+    pub fn is_discovered(&self, position: Position) -> bool {
+        self.remembered.contains_key(&position)
+    }
+
+    // Everything the observer sees right now
+    pub fn visible(&self) -> &HashSet<Position> {
+        &self.visible
+    }
+
+    pub fn remembered(&self, position: Position) -> Option<&RememberedTile> {
+        self.remembered.get(&position)
+    }
+
+    pub fn remembered_tiles(&self) -> impl Iterator<Item = (&Position, &RememberedTile)> {
+        self.remembered.iter()
+    }
+
+    // Brings back a memory without seeing the tile
+    pub(crate) fn remember_tile(&mut self, position: Position, remembered: RememberedTile) {
+        self.remembered.insert(position, remembered);
+    }
+
+    pub(crate) fn is_outdated(
+        &self,
+        viewpoints: &[Viewpoint],
+        grid: &Grid,
+        lighting: &Lighting,
+    ) -> bool {
+        self.flags_revision != Some(grid.flags_revision())
+            || self.lighting_revision != Some(lighting.revision())
+            || self.viewpoints != viewpoints
+    }
+
+    // Recomputes what is visible from the viewpoints and refreshes the memory of it,
+    // the tiles left in the dark can't be seen even when they are in sight.
+    // Returns the tiles that have never been seen before.
+    pub(crate) fn observe(
+        &mut self,
+        grid: &Grid,
+        actors: &ActorRegistry,
+        lighting: &Lighting,
+        viewpoints: Vec<Viewpoint>,
+    ) -> Vec<Position> {
+        let mut visible = HashSet::new();
+        for viewpoint in &viewpoints {
+            visible.extend(
+                grid.field_of_view(viewpoint.origin, Some(viewpoint.radius))
+                    .into_iter()
+                    .filter(|x| lighting.map().is_lit(*x)),
+            );
+        }
+
+        let mut discovered = vec![];
+        for position in &visible {
+            if self.remember(grid, actors, *position) {
+                discovered.push(*position);
+            }
+        }
+
+        self.visible = visible;
+        self.viewpoints = viewpoints;
+        self.flags_revision = Some(grid.flags_revision());
+        self.lighting_revision = Some(lighting.revision());
+        discovered
+    }
+
+    // Refreshes the memory of the tiles that changed while staying in sight
+    pub(crate) fn glimpse(
+        &mut self,
+        grid: &Grid,
+        actors: &ActorRegistry,
+        positions: impl IntoIterator<Item = Position>,
+    ) {
+        for position in positions {
+            if self.is_visible(position) {
+                self.remember(grid, actors, position);
+            }
+        }
+    }
+
+    // True if the tile wasn't remembered before
+    fn remember(&mut self, grid: &Grid, actors: &ActorRegistry, position: Position) -> bool {
+        let Some(tile) = grid.get_tile(position) else {
+            return false;
+        };
+
+        let remembered = RememberedTile {
+            material: tile.material.clone(),
+            actor: tile
+                .occupier
+                .and_then(|x| actors.get(x))
+                .map(|x| x.actor.clone()),
+            feature: tile.feature,
+        };
+
+        self.remembered.insert(position, remembered).is_none()
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt::Display;
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{Actor, Grid, Knowledge, Lighting, Observer, Position};
+
+#[derive(
+    Debug, Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct LevelId(pub u32);
+
+impl Display for LevelId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Where a staircase leads to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StairsLink {
+    pub level: LevelId,
+    pub position: Position,
+}
+
+// Makes up new levels the first time someone takes unexplored stairs
+pub trait LevelGenerator {
+    // Shapes the grid of the new level and picks where the stairs leading back are.
+    // Any other stairs on the grid lead further, to levels that don't exist yet.
+    fn generate(&mut self, level: LevelId) -> Option<(Grid, Position)>;
+
+    // Actors that live on the new level from the start
+    fn populate(&mut self, _level: LevelId, _grid: &Grid) -> Vec<(Position, Actor)> {
+        vec![]
+    }
+}
+
+// Everything that belongs to a level, kept aside while the player is elsewhere
+#[derive(Debug, Default)]
+pub(crate) struct Level {
+    pub(crate) grid: Grid,
+    pub(crate) knowledge: HashMap<Observer, Knowledge>,
+    pub(crate) lighting: Lighting,
+    pub(crate) stairs: HashMap<Position, StairsLink>,
+}
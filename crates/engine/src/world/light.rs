@@ -0,0 +1,194 @@
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{AsPosition, Grid, Position};
+
+// Tiles with less light than this on every channel can't be seen
+pub const LIT_THRESHOLD: f32 = 0.05;
+
+// How the light fades towards the edge of its radius
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Falloff {
+    Constant,
+    #[default]
+    Linear,
+    Quadratic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct LightSource {
+    pub radius: f32,
+    pub color: [u8; 3],
+    pub falloff: Falloff,
+}
+
+impl LightSource {
+    pub fn new(radius: f32, color: [u8; 3]) -> Self {
+        Self {
+            radius,
+            color,
+            falloff: Falloff::default(),
+        }
+    }
+
+    pub fn with_falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    // Share of the color that reaches a tile this far away
+    pub fn intensity_at(&self, distance: f32) -> f32 {
+        if distance > self.radius {
+            return 0.;
+        }
+
+        // The edge of the radius is still slightly lit
+        let t = distance / (self.radius + 1.);
+        match self.falloff {
+            Falloff::Constant => 1.,
+            Falloff::Linear => 1. - t,
+            Falloff::Quadratic => (1. - t).powi(2),
+        }
+    }
+}
+
+// Light on every tile, each channel is 1 at full brightness but can go over it
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LightMap {
+    levels: HashMap<Position, [f32; 3]>,
+    ambient: [f32; 3],
+}
+
+impl LightMap {
+    pub fn new(ambient: [f32; 3]) -> Self {
+        Self {
+            levels: HashMap::default(),
+            ambient,
+        }
+    }
+
+    pub fn ambient(&self) -> [f32; 3] {
+        self.ambient
+    }
+
+    // Lights up everything the source can see, walls stop the light like they stop sight
+    pub fn add_light(&mut self, grid: &Grid, origin: impl AsPosition, source: &LightSource) {
+        let origin = origin.into();
+        let color = source.color.map(|x| x as f32 / 255.);
+
+        for position in grid.field_of_view(origin, Some(source.radius)) {
+            let offset = position - origin;
+            let distance = ((offset.x * offset.x + offset.y * offset.y) as f32).sqrt();
+            let intensity = source.intensity_at(distance);
+            if intensity <= 0. {
+                continue;
+            }
+
+            let level = self.levels.entry(position).or_default();
+            for (level, color) in level.iter_mut().zip(color) {
+                *level += color * intensity;
+            }
+        }
+    }
+
+    pub fn light_at(&self, position: impl AsPosition) -> [f32; 3] {
+        let mut light = self.ambient;
+        if let Some(level) = self.levels.get(&position.into()) {
+            for (light, level) in light.iter_mut().zip(level) {
+                *light += level;
+            }
+        }
+        light
+    }
+
+    pub fn is_lit(&self, position: impl AsPosition) -> bool {
+        self.light_at(position)
+            .into_iter()
+            .any(|x| x >= LIT_THRESHOLD)
+    }
+}
+
+impl Grid {
+    // Returns the light that was there before
+    pub fn place_light(
+        &mut self,
+        position: impl AsPosition,
+        source: LightSource,
+    ) -> Option<LightSource> {
+        self.lights.insert(position.into(), source)
+    }
+
+    pub fn remove_light(&mut self, position: impl AsPosition) -> Option<LightSource> {
+        self.lights.remove(&position.into())
+    }
+
+    pub fn lights(&self) -> impl Iterator<Item = (Position, &LightSource)> {
+        self.lights.iter().map(|(x, y)| (*x, y))
+    }
+
+    pub fn light_map<'a>(
+        &self,
+        ambient: [f32; 3],
+        sources: impl IntoIterator<Item = (Position, &'a LightSource)>,
+    ) -> LightMap {
+        let mut map = LightMap::new(ambient);
+        for (origin, source) in sources {
+            map.add_light(self, origin, source);
+        }
+        map
+    }
+}
+
+// The light map of the world, only recomputed when a light or the tiles change
+#[derive(Debug, Clone)]
+pub struct Lighting {
+    map: LightMap,
+    sources: Vec<(Position, LightSource)>,
+    flags_revision: Option<u64>,
+    revision: u64,
+}
+
+impl Default for Lighting {
+    // Everything is lit until told otherwise
+    fn default() -> Self {
+        Self::new([1.; 3])
+    }
+}
+
+impl Lighting {
+    pub fn new(ambient: [f32; 3]) -> Self {
+        Self {
+            map: LightMap::new(ambient),
+            sources: vec![],
+            flags_revision: None,
+            revision: 0,
+        }
+    }
+
+    pub fn map(&self) -> &LightMap {
+        &self.map
+    }
+
+    // Bumped every time the light map changes
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub(crate) fn set_ambient(&mut self, grid: &Grid, ambient: [f32; 3]) {
+        self.flags_revision = None;
+        self.map.ambient = ambient;
+        self.update(grid, self.sources.clone());
+    }
+
+    pub(crate) fn update(&mut self, grid: &Grid, sources: Vec<(Position, LightSource)>) {
+        if self.flags_revision == Some(grid.flags_revision()) && self.sources == sources {
+            return;
+        }
+
+        self.map = grid.light_map(self.map.ambient, sources.iter().map(|(x, y)| (*x, y)));
+        self.sources = sources;
+        self.flags_revision = Some(grid.flags_revision());
+        self.revision += 1;
+    }
+}
@@ -6,13 +6,23 @@ bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
     #[repr(C)]
     pub struct MaterialFlags: u16 {
-        const PASSTHROUGH       = 0b000001;
-        const SIGHTBLOCKER      = 0b000010;
+        const WALKABLE              = 0b000001;
+        const TRANSPARENT           = 0b000010;
+        const BLOCKS_PROJECTILES    = 0b000100;
+        const FLYABLE               = 0b001000;
+        const SWIMMABLE             = 0b010000;
+        const DIGGABLE              = 0b100000;
 
-        const SOLID             = 0b000010;
+        const FLOOR                 = Self::WALKABLE.bits() | Self::TRANSPARENT.bits() | Self::FLYABLE.bits();
+        const WALL                  = Self::BLOCKS_PROJECTILES.bits() | Self::DIGGABLE.bits();
+        const WATER                 = Self::TRANSPARENT.bits() | Self::FLYABLE.bits() | Self::SWIMMABLE.bits();
+        const CHASM                 = Self::TRANSPARENT.bits() | Self::FLYABLE.bits();
     }
 }
 
+// Cost of stepping onto a material unless it says otherwise
+pub const DEFAULT_MOVEMENT_COST: u32 = 1;
+
 #[non_exhaustive]
 #[derive(Debug, PartialEq, Eq)]
 pub struct Material {
@@ -20,6 +30,7 @@ pub struct Material {
     pub resource_name: String,
     pub obscured_resource_name: Option<String>,
     pub flags: MaterialFlags,
+    pub movement_cost: u32,
 }
 
 impl Material {
@@ -28,12 +39,42 @@ impl Material {
         resource_name: impl ToString,
         obscured_resource_name: Option<impl ToString>,
         flags: MaterialFlags,
-    ) -> MaterialHandle {
-        Rc::new(Material {
+    ) -> Self {
+        Material {
             display_name: display_name.to_string(),
             resource_name: resource_name.to_string(),
             obscured_resource_name: obscured_resource_name.as_ref().map(ToString::to_string),
             flags,
-        })
+            movement_cost: DEFAULT_MOVEMENT_COST,
+        }
+    }
+
+    pub fn with_movement_cost(mut self, movement_cost: u32) -> Self {
+        self.movement_cost = movement_cost;
+        self
+    }
+
+    pub fn is_walkable(&self) -> bool {
+        self.flags.contains(MaterialFlags::WALKABLE)
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.flags.contains(MaterialFlags::TRANSPARENT)
+    }
+
+    pub fn blocks_projectiles(&self) -> bool {
+        self.flags.contains(MaterialFlags::BLOCKS_PROJECTILES)
+    }
+
+    pub fn is_flyable(&self) -> bool {
+        self.flags.contains(MaterialFlags::FLYABLE)
+    }
+
+    pub fn is_swimmable(&self) -> bool {
+        self.flags.contains(MaterialFlags::SWIMMABLE)
+    }
+
+    pub fn is_diggable(&self) -> bool {
+        self.flags.contains(MaterialFlags::DIGGABLE)
     }
 }
@@ -1,11 +1,40 @@
 mod action;
 mod actor;
+mod arena;
+mod catalog;
+mod component;
+mod dijkstra;
+mod event;
+mod feature;
+mod fov;
 mod grid;
+mod knowledge;
+mod level;
+mod light;
 mod material;
+mod path;
+mod registry;
+mod save;
+mod scheduler;
+#[allow(clippy::module_inception)]
 mod world;
 
 pub use action::*;
 pub use actor::*;
+pub use arena::*;
+pub use catalog::*;
+pub use component::*;
+pub use dijkstra::*;
+pub use event::*;
+pub use feature::*;
+pub use fov::*;
 pub use grid::*;
+pub use knowledge::*;
+pub use level::*;
+pub use light::*;
 pub use material::*;
+pub use path::*;
+pub use registry::*;
+pub use save::*;
+pub use scheduler::*;
 pub use world::*;
@@ -0,0 +1,151 @@
+use pathfinding::directed::astar::astar;
+
+use crate::{AsPosition, Grid, MaterialFlags, MaterialHandle, Position, Tile};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Movement {
+    // Only orthogonal steps
+    #[default]
+    Neumann,
+    // Orthogonal and diagonal steps
+    Moore,
+}
+
+impl Movement {
+    // Least amount of steps between the two positions
+    pub fn distance(&self, from: impl AsPosition, to: impl AsPosition) -> u32 {
+        let offset: Position = to.into() - from.into();
+        let (dx, dy) = (offset.x.unsigned_abs(), offset.y.unsigned_abs());
+        match self {
+            Movement::Neumann => dx + dy,
+            Movement::Moore => dx.max(dy),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct PathOptions {
+    pub movement: Movement,
+    // Any of these flags make a material traversable
+    pub traversal: MaterialFlags,
+    // Cost of stepping onto a material, its own movement cost for anything not listed
+    pub material_costs: Vec<(MaterialHandle, u32)>,
+    // Extra cost of walking through an occupied tile,
+    // occupied tiles are impassable if there is none
+    pub occupied_cost: Option<u32>,
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        Self {
+            movement: Movement::default(),
+            traversal: MaterialFlags::WALKABLE,
+            material_costs: vec![],
+            occupied_cost: None,
+        }
+    }
+}
+
+impl PathOptions {
+    pub fn new(movement: Movement) -> Self {
+        Self {
+            movement,
+            ..Default::default()
+        }
+    }
+
+    // Flying or swimming actors get through materials walkers can't
+    pub fn with_traversal(mut self, traversal: MaterialFlags) -> Self {
+        self.traversal = traversal;
+        self
+    }
+
+    pub fn with_material_cost(mut self, material: MaterialHandle, cost: u32) -> Self {
+        self.material_costs.push((material, cost));
+        self
+    }
+
+    pub fn with_occupied_cost(mut self, cost: u32) -> Self {
+        self.occupied_cost = Some(cost);
+        self
+    }
+
+    // Cost of stepping onto the tile, None if it can't be stepped on.
+    // Every step costs at least 1 so the distance stays a valid heuristic.
+    pub fn step_cost(&self, tile: &Tile) -> Option<u32> {
+        let occupied_cost = match (tile.is_occupied(), self.occupied_cost) {
+            (false, _) => 0,
+            (true, Some(cost)) => cost,
+            (true, None) => return None,
+        };
+
+        self.terrain_cost(tile).map(|cost| cost + occupied_cost)
+    }
+
+    // Cost of stepping onto the tile if nobody was standing on it
+    pub fn terrain_cost(&self, tile: &Tile) -> Option<u32> {
+        if !tile.is_traversable(self.traversal) {
+            return None;
+        }
+
+        let material_cost = self
+            .material_costs
+            .iter()
+            .find(|(material, _)| *material == tile.material)
+            .map_or(tile.material.movement_cost, |(_, cost)| *cost);
+
+        Some(material_cost.max(1))
+    }
+
+    pub(crate) fn neighbours<'a>(
+        &self,
+        grid: &'a Grid,
+        at: Position,
+    ) -> impl Iterator<Item = (Position, &'a Tile)> {
+        let neighbours = match self.movement {
+            Movement::Neumann => grid.tile_neumann_neighbours(at).to_vec(),
+            Movement::Moore => grid.tile_moore_neighbours(at).to_vec(),
+        };
+
+        neighbours
+            .into_iter()
+            .filter_map(|(pos, tile)| tile.map(|tile| (pos, tile)))
+    }
+}
+
+impl Grid {
+    // A* from one tile to another, returns the path including both ends and its cost.
+    // The destination may be occupied, so actors can path towards each other, and costs
+    // as much as its terrain does. There is no path to a destination that can't be traversed.
+    pub fn find_path(
+        &self,
+        from: impl AsPosition,
+        to: impl AsPosition,
+        options: &PathOptions,
+    ) -> Option<(Vec<Position>, u32)> {
+        let (from, to) = (from.into(), to.into());
+        let destination = self.get_tile(to)?;
+        if !destination.is_traversable(options.traversal) {
+            return None;
+        }
+
+        astar(
+            &from,
+            |&at| {
+                options
+                    .neighbours(self, at)
+                    .filter_map(|(pos, tile)| {
+                        // Only who stands on the destination is overlooked, not what it is made of
+                        if pos == to {
+                            return options.terrain_cost(tile).map(|cost| (pos, cost));
+                        }
+                        options.step_cost(tile).map(|cost| (pos, cost))
+                    })
+                    .collect::<Vec<_>>()
+            },
+            |&at| options.movement.distance(at, to),
+            |&at| at == to,
+        )
+    }
+}
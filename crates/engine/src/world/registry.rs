@@ -0,0 +1,147 @@
+use std::rc::Rc;
+
+use hashbrown::HashMap;
+use smallvec::SmallVec;
+
+use crate::{Actor, ActorData, ActorId, ActorTemplate, Arena, AsPosition, LevelId, Position};
+
+// Side length of the square buckets actors are indexed by
+const CHUNK_SIZE: i32 = 8;
+
+fn chunk_of(level: LevelId, position: Position) -> (LevelId, Position) {
+    (
+        level,
+        Position::new(
+            position.x.div_euclid(CHUNK_SIZE),
+            position.y.div_euclid(CHUNK_SIZE),
+        ),
+    )
+}
+
+// Owns every actor in the world, on every level, and keeps them indexed by position
+#[derive(Debug, Default)]
+pub struct ActorRegistry {
+    actors: Arena<ActorData>,
+    chunks: HashMap<(LevelId, Position), SmallVec<[ActorId; 4]>>,
+}
+
+impl ActorRegistry {
+    pub fn len(&self) -> usize {
+        self.actors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actors.is_empty()
+    }
+
+    pub fn insert(&mut self, actor: Actor, level: LevelId, position: impl AsPosition) -> ActorId {
+        let position = position.into();
+        let id = self.actors.insert(ActorData::new(actor, level, position));
+        self.chunks
+            .entry(chunk_of(level, position))
+            .or_default()
+            .push(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: ActorId) -> Option<ActorData> {
+        let data = self.actors.remove(id)?;
+        self.unindex(id, chunk_of(data.level(), data.position()));
+        Some(data)
+    }
+
+    // Updates the position of the actor, the world keeps the grid in sync
+    pub(crate) fn relocate(&mut self, id: ActorId, to: impl AsPosition) {
+        if let Some(level) = self.actors.get(id).map(ActorData::level) {
+            self.transfer(id, level, to);
+        }
+    }
+
+    // Moves the actor to a position on another level, the world keeps the grids in sync
+    pub(crate) fn transfer(&mut self, id: ActorId, level: LevelId, to: impl AsPosition) {
+        let to = to.into();
+        let Some(data) = self.actors.get_mut(id) else {
+            return;
+        };
+
+        let from = chunk_of(data.level, data.position);
+        data.level = level;
+        data.position = to;
+
+        if from != chunk_of(level, to) {
+            self.unindex(id, from);
+            self.chunks.entry(chunk_of(level, to)).or_default().push(id);
+        }
+    }
+
+    pub fn contains(&self, id: ActorId) -> bool {
+        self.actors.contains(id)
+    }
+
+    pub fn get(&self, id: ActorId) -> Option<&ActorData> {
+        self.actors.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: ActorId) -> Option<&mut ActorData> {
+        self.actors.get_mut(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ActorId, &ActorData)> {
+        self.actors.iter()
+    }
+
+    pub fn on_level(&self, level: LevelId) -> impl Iterator<Item = (ActorId, &ActorData)> {
+        self.iter().filter(move |(_, data)| data.level() == level)
+    }
+
+    pub fn with_template<'a>(
+        &'a self,
+        template: &'a Rc<ActorTemplate>,
+    ) -> impl Iterator<Item = (ActorId, &'a ActorData)> {
+        self.iter()
+            .filter(move |(_, data)| Rc::ptr_eq(data.actor().template_handle(), template))
+    }
+
+    pub fn at(&self, level: LevelId, position: impl AsPosition) -> Option<ActorId> {
+        let position = position.into();
+        self.chunks
+            .get(&chunk_of(level, position))?
+            .iter()
+            .copied()
+            .find(|id| self.actors.get(*id).map(ActorData::position) == Some(position))
+    }
+
+    // Every actor on the level no further than `radius` tiles away from `center`
+    pub fn within_radius(
+        &self,
+        level: LevelId,
+        center: impl AsPosition,
+        radius: u32,
+    ) -> impl Iterator<Item = (ActorId, &ActorData)> {
+        let center = center.into();
+        let radius = radius as i32;
+        let ((_, min), (_, max)) = (
+            chunk_of(level, center - Position::new(radius, radius)),
+            chunk_of(level, center + Position::new(radius, radius)),
+        );
+
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| (level, Position::new(x, y))))
+            .filter_map(|chunk| self.chunks.get(&chunk))
+            .flatten()
+            .filter_map(|id| self.actors.get(*id).map(|data| (*id, data)))
+            .filter(move |(_, data)| {
+                let offset = data.position() - center;
+                offset.x * offset.x + offset.y * offset.y <= radius * radius
+            })
+    }
+
+    fn unindex(&mut self, id: ActorId, chunk: (LevelId, Position)) {
+        if let Some(bucket) = self.chunks.get_mut(&chunk) {
+            bucket.retain(|x| *x != id);
+            if bucket.is_empty() {
+                self.chunks.remove(&chunk);
+            }
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use std::io::{Read, Write};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    ActionError, Actor, ActorId, Catalog, Faction, Feature, Grid, Knowledge, Level, LevelId,
+    LightSource, Lighting, MaterialId, Observer, Position, RememberedTile, StairsLink, TemplateId,
+    World,
+};
+
+// Bumped every time the layout of the save changes
+pub const SAVE_VERSION: u32 = 2;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SaveError {
+    #[error("saves of version {0} aren't supported, expected version {SAVE_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("material \"{0}\" has no id in the catalog")]
+    UnregisteredMaterial(String),
+    #[error("actor template \"{0}\" has no id in the catalog")]
+    UnregisteredTemplate(String),
+    #[error("there is no material with id \"{0}\" in the catalog")]
+    UnknownMaterial(MaterialId),
+    #[error("there is no actor template with id \"{0}\" in the catalog")]
+    UnknownTemplate(TemplateId),
+    #[error("the save refers to actor #{0}, which isn't in it")]
+    UnknownActor(usize),
+    #[error("the save refers to level {0}, which isn't in it")]
+    UnknownLevel(LevelId),
+    #[error("actor #{0} can't be placed where the save says: {1}")]
+    Misplaced(usize, ActionError),
+    #[error("the save is malformed: {0}")]
+    Format(#[from] ron::error::SpannedError),
+    #[error("couldn't write the save: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+// Checked before anything else, so older saves fail with a clear error
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+// Everything is kept sorted, so saving the same world twice gives the same bytes
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    level: LevelId,
+    next_level: u32,
+    // Index into `actors`
+    player: Option<usize>,
+    levels: Vec<LevelSave>,
+    actors: Vec<ActorSave>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LevelSave {
+    id: LevelId,
+    size: [i32; 2],
+    ambient_light: [f32; 3],
+    tiles: Vec<TileSave>,
+    lights: Vec<([i32; 2], LightSource)>,
+    stairs: Vec<([i32; 2], LevelId, [i32; 2])>,
+    knowledge: Vec<(ObserverSave, Vec<MemorySave>)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TileSave {
+    position: [i32; 2],
+    material: MaterialId,
+    feature: Option<Feature>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ActorSave {
+    template: TemplateId,
+    level: LevelId,
+    position: [i32; 2],
+    energy: i32,
+    health: i32,
+    // The only component that is saved, everyone would be in the dark without it
+    light: Option<LightSource>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum ObserverSave {
+    // Index into `actors`
+    Actor(usize),
+    Faction(u16),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MemorySave {
+    position: [i32; 2],
+    material: MaterialId,
+    actor: Option<TemplateId>,
+    feature: Option<Feature>,
+}
+
+fn to_array(position: Position) -> [i32; 2] {
+    [position.x, position.y]
+}
+
+impl World {
+    // Writes every level, actor and what the observers remember in a versioned text format.
+    // Of the components only light sources are saved. The rest and the level generator
+    // aren't, the game has to set them up again.
+    pub fn save(&self, catalog: &Catalog, writer: impl Write) -> Result<(), SaveError> {
+        let mut actors = self.actors.iter().collect::<Vec<_>>();
+        actors.sort_by_key(|(id, _)| *id);
+        let indices = actors
+            .iter()
+            .enumerate()
+            .map(|(index, (id, _))| (*id, index))
+            .collect::<HashMap<_, _>>();
+
+        let material_id = |material| {
+            catalog
+                .material_id(material)
+                .cloned()
+                .ok_or_else(|| SaveError::UnregisteredMaterial(material.display_name.clone()))
+        };
+        let template_id = |actor: &Actor| {
+            catalog
+                .template_id(actor.template_handle())
+                .cloned()
+                .ok_or_else(|| SaveError::UnregisteredTemplate(actor.template().name().to_string()))
+        };
+
+        let mut levels = vec![];
+        for id in self.levels() {
+            let (grid, knowledge, lighting, stairs) = match self.levels.get(&id) {
+                Some(level) => (
+                    &level.grid,
+                    &level.knowledge,
+                    &level.lighting,
+                    &level.stairs,
+                ),
+                None => (&self.grid, &self.knowledge, &self.lighting, &self.stairs),
+            };
+
+            let mut tiles = grid
+                .tiles
+                .values()
+                .map(|tile| {
+                    Ok(TileSave {
+                        position: to_array(tile.position),
+                        material: material_id(&tile.material)?,
+                        feature: tile.feature,
+                    })
+                })
+                .collect::<Result<Vec<_>, SaveError>>()?;
+            tiles.sort_by_key(|x| [x.position[1], x.position[0]]);
+
+            let mut lights = grid
+                .lights()
+                .map(|(position, light)| (to_array(position), *light))
+                .collect::<Vec<_>>();
+            lights.sort_by_key(|(position, _)| [position[1], position[0]]);
+
+            let mut stairs = stairs
+                .iter()
+                .map(|(from, to)| (to_array(*from), to.level, to_array(to.position)))
+                .collect::<Vec<_>>();
+            stairs.sort_by_key(|(position, _, _)| [position[1], position[0]]);
+
+            let mut memories = vec![];
+            for (observer, knowledge) in knowledge {
+                let observer = match observer {
+                    Observer::Actor(actor) => match indices.get(actor) {
+                        Some(index) => ObserverSave::Actor(*index),
+                        // Whoever it was is gone, nobody will ask for it again
+                        None => continue,
+                    },
+                    Observer::Faction(Faction(faction)) => ObserverSave::Faction(*faction),
+                };
+
+                let mut remembered = knowledge
+                    .remembered_tiles()
+                    .map(|(position, memory)| {
+                        Ok(MemorySave {
+                            position: to_array(*position),
+                            material: material_id(&memory.material)?,
+                            actor: memory.actor.as_ref().map(template_id).transpose()?,
+                            feature: memory.feature,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, SaveError>>()?;
+                remembered.sort_by_key(|x| [x.position[1], x.position[0]]);
+                memories.push((observer, remembered));
+            }
+            memories.sort_by(|a, b| a.0.cmp(&b.0));
+
+            levels.push(LevelSave {
+                id,
+                size: to_array(grid.size),
+                ambient_light: lighting.map().ambient(),
+                tiles,
+                lights,
+                stairs,
+                knowledge: memories,
+            });
+        }
+        levels.sort_by_key(|x| x.id);
+
+        let actors = actors
+            .iter()
+            .map(|(id, data)| {
+                Ok(ActorSave {
+                    template: template_id(data.actor())?,
+                    level: data.level(),
+                    position: to_array(data.position()),
+                    energy: data.energy,
+                    health: data.health,
+                    light: self.component::<LightSource>(*id).copied(),
+                })
+            })
+            .collect::<Result<Vec<_>, SaveError>>()?;
+
+        let save = SaveFile {
+            version: SAVE_VERSION,
+            level: self.level,
+            next_level: self.next_level,
+            player: self.player.and_then(|x| indices.get(&x).copied()),
+            levels,
+            actors,
+        };
+
+        ron::ser::to_writer_pretty(writer, &save, ron::ser::PrettyConfig::default())?;
+        Ok(())
+    }
+
+    pub fn load(catalog: &Catalog, mut reader: impl Read) -> Result<World, SaveError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let header: SaveHeader = ron::from_str(&text)?;
+        if header.version != SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(header.version));
+        }
+        let save: SaveFile = ron::from_str(&text)?;
+
+        let material = |id: &MaterialId| {
+            catalog
+                .material(id)
+                .cloned()
+                .ok_or_else(|| SaveError::UnknownMaterial(id.clone()))
+        };
+        let actor = |id: &TemplateId| {
+            catalog
+                .template(id)
+                .cloned()
+                .map(Actor::from_template)
+                .ok_or_else(|| SaveError::UnknownTemplate(id.clone()))
+        };
+
+        let mut world = World::new(0, 0);
+        world.level = save.level;
+        world.next_level = save.next_level;
+
+        let mut levels = HashMap::new();
+        for level in &save.levels {
+            let mut grid = Grid::new(level.size[0] as u16, level.size[1] as u16);
+            for tile in &level.tiles {
+                grid.make_tile_at(tile.position, material(&tile.material)?);
+                grid.get_tile_mut(tile.position)
+                    .expect("Tile was just made")
+                    .feature = tile.feature;
+            }
+            for (position, light) in &level.lights {
+                grid.place_light(*position, *light);
+            }
+            grid.take_events();
+
+            let stairs = level
+                .stairs
+                .iter()
+                .map(|(from, level, to)| {
+                    let link = StairsLink {
+                        level: *level,
+                        position: (*to).into(),
+                    };
+                    ((*from).into(), link)
+                })
+                .collect();
+
+            levels.insert(
+                level.id,
+                Level {
+                    grid,
+                    knowledge: HashMap::new(),
+                    lighting: Lighting::new(level.ambient_light),
+                    stairs,
+                },
+            );
+        }
+
+        let mut ids = vec![];
+        for saved in &save.actors {
+            let grid = &mut levels
+                .get_mut(&saved.level)
+                .ok_or(SaveError::UnknownLevel(saved.level))?
+                .grid;
+
+            let id = world
+                .actors
+                .insert(actor(&saved.template)?, saved.level, saved.position);
+            let data = world.actors.get_mut(id).expect("Actor was just inserted");
+            data.energy = saved.energy;
+            data.health = saved.health;
+
+            grid.put_actor(saved.position, id)
+                .map_err(|e| SaveError::Misplaced(ids.len(), e))?;
+            grid.take_events();
+            if let Some(light) = saved.light {
+                world
+                    .insert_component(id, light)
+                    .expect("Actor was just inserted");
+            }
+            ids.push(id);
+        }
+
+        let actor_id = |index: usize| -> Result<ActorId, SaveError> {
+            ids.get(index)
+                .copied()
+                .ok_or(SaveError::UnknownActor(index))
+        };
+
+        for saved in &save.levels {
+            let level = levels.get_mut(&saved.id).expect("Level was just loaded");
+            for (observer, memories) in &saved.knowledge {
+                let observer = match observer {
+                    ObserverSave::Actor(index) => Observer::Actor(actor_id(*index)?),
+                    ObserverSave::Faction(faction) => Observer::Faction(Faction(*faction)),
+                };
+
+                let knowledge = level
+                    .knowledge
+                    .entry(observer)
+                    .or_insert_with(Knowledge::default);
+                for memory in memories {
+                    let remembered = RememberedTile {
+                        material: material(&memory.material)?,
+                        actor: memory.actor.as_ref().map(actor).transpose()?,
+                        feature: memory.feature,
+                    };
+                    knowledge.remember_tile(memory.position.into(), remembered);
+                }
+            }
+        }
+
+        let current = levels
+            .remove(&save.level)
+            .ok_or(SaveError::UnknownLevel(save.level))?;
+        world.grid = current.grid;
+        world.knowledge = current.knowledge;
+        world.lighting = current.lighting;
+        world.stairs = current.stairs;
+        world.levels = levels;
+
+        world.player = save.player.map(actor_id).transpose()?;
+        for (id, _) in world.actors.on_level(world.level) {
+            world.scheduler.schedule(id);
+        }
+
+        world.update_knowledge();
+        world.drain_events();
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{ActorTemplate, FeatureState, Material, MaterialFlags, MaterialHandle};
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::default();
+        catalog.insert_material(
+            MaterialId("floor".to_string()),
+            Rc::new(Material::new(
+                "Floor",
+                "floor",
+                None::<String>,
+                MaterialFlags::FLOOR,
+            )),
+        );
+        catalog.insert_material(
+            MaterialId("wall".to_string()),
+            Rc::new(Material::new(
+                "Wall",
+                "wall",
+                None::<String>,
+                MaterialFlags::WALL,
+            )),
+        );
+        catalog.insert_template(
+            TemplateId("player".to_string()),
+            Rc::new(ActorTemplate::new("Player", "player")),
+        );
+        catalog
+    }
+
+    fn material(catalog: &Catalog, id: &str) -> MaterialHandle {
+        catalog
+            .material(&MaterialId(id.to_string()))
+            .expect("Material is in the catalog")
+            .clone()
+    }
+
+    fn room(catalog: &Catalog) -> Grid {
+        let mut grid = Grid::new(8, 8);
+        grid.make_tile_box([0, 0], [8, 8], material(catalog, "wall"));
+        grid.make_tile_box([1, 1], [7, 7], material(catalog, "floor"));
+        grid
+    }
+
+    // A player on the first level with a light, a door, a lamp and stairs to a second level
+    fn world(catalog: &Catalog) -> World {
+        let mut world = World::new(0, 0);
+        world.grid = room(catalog);
+        world
+            .grid
+            .set_feature([4, 1], Some(Feature::door(FeatureState::Closed)))
+            .unwrap();
+        world
+            .grid
+            .place_light([5, 5], LightSource::new(3., [255, 0, 0]));
+        world.set_ambient_light([0.5; 3]);
+
+        let template = catalog
+            .template(&TemplateId("player".to_string()))
+            .unwrap()
+            .clone();
+        let player = world
+            .spawn_player([2, 2], Actor::from_template(template))
+            .unwrap();
+        world
+            .insert_component(player, LightSource::new(4., [255, 220, 170]))
+            .unwrap();
+        world.track_knowledge(Observer::Actor(player));
+
+        let below = world.add_level(room(catalog));
+        world
+            .link_stairs(
+                StairsLink {
+                    level: world.level(),
+                    position: [6, 6].into(),
+                },
+                StairsLink {
+                    level: below,
+                    position: [1, 1].into(),
+                },
+            )
+            .unwrap();
+        world.update_knowledge();
+        world
+    }
+
+    fn save(world: &World, catalog: &Catalog) -> String {
+        let mut bytes = vec![];
+        world.save(catalog, &mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn round_trip_keeps_everything() {
+        let catalog = catalog();
+        let world = world(&catalog);
+        let saved = save(&world, &catalog);
+
+        let loaded = World::load(&catalog, saved.as_bytes()).unwrap();
+        assert_eq!(save(&loaded, &catalog), saved);
+
+        let player = loaded.player().expect("The player was saved");
+        assert_eq!(
+            loaded.actor(player).unwrap().position(),
+            Position::new(2, 2)
+        );
+        assert_eq!(
+            loaded.component::<LightSource>(player),
+            Some(&LightSource::new(4., [255, 220, 170]))
+        );
+
+        let door = loaded.grid.get_tile([4, 1]).unwrap();
+        assert_eq!(door.feature, Some(Feature::door(FeatureState::Closed)));
+        assert!(Rc::ptr_eq(&door.material, &material(&catalog, "floor")));
+        assert_eq!(
+            loaded.grid.lights().collect::<Vec<_>>(),
+            vec![([5, 5].into(), &LightSource::new(3., [255, 0, 0]))]
+        );
+
+        let link = loaded.stairs([6, 6]).expect("The stairs were saved");
+        let below = loaded.level_grid(link.level).unwrap();
+        assert_eq!(link.position, Position::new(1, 1));
+        assert_eq!(
+            below.get_tile([1, 1]).unwrap().feature,
+            Some(Feature::stairs())
+        );
+
+        let knowledge = loaded.knowledge(Observer::Actor(player)).unwrap();
+        assert!(knowledge.is_discovered([4, 1].into()));
+        assert_eq!(
+            knowledge.remembered([4, 1].into()).unwrap().feature,
+            Some(Feature::door(FeatureState::Closed))
+        );
+    }
+
+    #[test]
+    fn load_rejects_other_versions() {
+        let save = format!("(version: {})", SAVE_VERSION + 1);
+        assert!(matches!(
+            World::load(&catalog(), save.as_bytes()),
+            Err(SaveError::UnsupportedVersion(x)) if x == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn load_rejects_unknown_ids() {
+        let catalog = catalog();
+        let saved = save(&world(&catalog), &catalog);
+
+        let mut without_wall = Catalog::default();
+        let mut without_player = Catalog::default();
+        for (id, material) in catalog.materials() {
+            if id.0 != "wall" {
+                without_wall.insert_material(id.clone(), material.clone());
+            }
+            without_player.insert_material(id.clone(), material.clone());
+        }
+
+        assert!(matches!(
+            World::load(&without_wall, saved.as_bytes()),
+            Err(SaveError::UnknownMaterial(MaterialId(x))) if x == "wall"
+        ));
+        assert!(matches!(
+            World::load(&without_player, saved.as_bytes()),
+            Err(SaveError::UnknownTemplate(TemplateId(x))) if x == "player"
+        ));
+    }
+}
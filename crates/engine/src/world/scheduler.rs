@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use crate::{Action, ActorId, ActorRegistry, World};
+
+// Energy an actor has to accumulate before it gets to take a turn
+pub const TURN_ENERGY: i32 = 100;
+
+// Decides what a non-player actor does when its turn comes
+pub trait Controller {
+    fn decide(&mut self, world: &World, actor: ActorId) -> Action;
+}
+
+impl<F> Controller for F
+where
+    F: FnMut(&World, ActorId) -> Action,
+{
+    fn decide(&mut self, world: &World, actor: ActorId) -> Action {
+        self(world, actor)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    actors: VecDeque<ActorId>,
+}
+
+impl Scheduler {
+    pub fn schedule(&mut self, actor: ActorId) {
+        if !self.actors.contains(&actor) {
+            self.actors.push_back(actor);
+        }
+    }
+
+    pub fn unschedule(&mut self, actor: ActorId) {
+        self.actors.retain(|x| *x != actor);
+    }
+
+    pub fn is_scheduled(&self, actor: ActorId) -> bool {
+        self.actors.contains(&actor)
+    }
+
+    // The actor whose turn it is, without handing out any energy
+    pub fn ready(&self, actors: &ActorRegistry) -> Option<ActorId> {
+        self.actors.iter().copied().find(|actor| {
+            actors
+                .get(*actor)
+                .map_or(false, |data| data.energy >= TURN_ENERGY)
+        })
+    }
+
+    // Hands out energy until some actor can afford a turn.
+    // Actors that left the world are dropped from the queue.
+    pub fn next_ready(&mut self, actors: &mut ActorRegistry) -> Option<ActorId> {
+        self.actors.retain(|actor| actors.contains(*actor));
+
+        let can_progress = self
+            .actors
+            .iter()
+            .filter_map(|actor| actors.get(*actor))
+            .any(|data| data.template().speed() > 0);
+
+        loop {
+            if let Some(ready) = self.ready(actors) {
+                return Some(ready);
+            }
+
+            if !can_progress {
+                return None;
+            }
+
+            for actor in &self.actors {
+                if let Some(data) = actors.get_mut(*actor) {
+                    data.energy += data.template().speed();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Actor, ActorTemplate, LevelId};
+
+    #[test]
+    fn faster_actors_get_more_turns() {
+        let mut actors = ActorRegistry::default();
+        let mut spawn = |speed| {
+            let template = ActorTemplate::new("Snek", "creature.snek").with_speed(speed);
+            actors.insert(Actor::from_template(Rc::new(template)), LevelId(0), [0, 0])
+        };
+        let (slow, fast) = (spawn(10), spawn(20));
+
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(slow);
+        scheduler.schedule(fast);
+
+        let (mut slow_turns, mut fast_turns) = (0, 0);
+        for _ in 0..30 {
+            let actor = scheduler
+                .next_ready(&mut actors)
+                .expect("Both actors have speed");
+            actors.get_mut(actor).unwrap().energy -= TURN_ENERGY;
+            if actor == fast {
+                fast_turns += 1;
+            } else {
+                slow_turns += 1;
+            }
+        }
+
+        assert_eq!((slow_turns, fast_turns), (10, 20));
+    }
+}
@@ -1,41 +1,701 @@
-use crate::{Action, ActorReference, Grid, Position, Tile};
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+    Action, ActionError, ActionOutcome, ActionResult, Actor, ActorData, ActorId, ActorRegistry,
+    AsPosition, Component, ComponentStore, Controller, Event, EventBus, Feature, FeatureKind,
+    FeatureState, Grid, Knowledge, Level, LevelGenerator, LevelId, LightMap, LightSource, Lighting,
+    Observer, Position, Scheduler, StairsLink, SubscriptionId, Viewpoint, TURN_ENERGY,
+};
 
 pub struct World {
+    // Grid of the level the player is on
     pub grid: Grid,
+    pub(crate) actors: ActorRegistry,
+    components: ComponentStore,
+    pub(crate) scheduler: Scheduler,
+    pub(crate) player: Option<ActorId>,
+    events: EventBus,
+    pub(crate) knowledge: HashMap<Observer, Knowledge>,
+    pub(crate) lighting: Lighting,
+    // Where the stairs of the current level lead
+    pub(crate) stairs: HashMap<Position, StairsLink>,
+    pub(crate) level: LevelId,
+    // Every level but the current one, frozen until the player comes back
+    pub(crate) levels: HashMap<LevelId, Level>,
+    pub(crate) next_level: u32,
+    generator: Option<Box<dyn LevelGenerator>>,
 }
 
 impl World {
     pub fn new(width: u16, height: u16) -> Self {
         Self {
             grid: Grid::new(width, height),
+            actors: ActorRegistry::default(),
+            components: ComponentStore::default(),
+            scheduler: Scheduler::default(),
+            player: None,
+            events: EventBus::default(),
+            knowledge: HashMap::default(),
+            lighting: Lighting::default(),
+            stairs: HashMap::default(),
+            level: LevelId(0),
+            levels: HashMap::default(),
+            next_level: 1,
+            generator: None,
         }
     }
 
-    pub fn submit_action(&mut self, action: Action) -> bool {
-        match action {
-            Action::MoveActor { actor_ref, to } => self.move_actor(actor_ref, to),
-            _ => false,
+    pub fn subscribe(&mut self, callback: impl FnMut(&Event) + 'static) -> SubscriptionId {
+        self.events.subscribe(callback)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.events.unsubscribe(id)
+    }
+
+    // Notifies the subscribers about everything that happened since the last flush
+    pub fn flush_events(&mut self) {
+        self.events.publish(self.grid.take_events());
+    }
+
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, Event> {
+        self.flush_events();
+        self.events.drain()
+    }
+
+    pub fn player(&self) -> Option<ActorId> {
+        self.player
+    }
+
+    pub fn actors(&self) -> &ActorRegistry {
+        &self.actors
+    }
+
+    // The level the player is on
+    pub fn level(&self) -> LevelId {
+        self.level
+    }
+
+    pub fn levels(&self) -> impl Iterator<Item = LevelId> + '_ {
+        std::iter::once(self.level).chain(self.levels.keys().copied())
+    }
+
+    pub fn level_grid(&self, level: LevelId) -> Option<&Grid> {
+        match level == self.level {
+            true => Some(&self.grid),
+            false => self.levels.get(&level).map(|x| &x.grid),
+        }
+    }
+
+    // Adds a level nobody is on, it can be reached once stairs lead to it
+    pub fn add_level(&mut self, grid: Grid) -> LevelId {
+        let id = LevelId(self.next_level);
+        self.next_level += 1;
+
+        // Nobody is there to notice the changes
+        grid.take_events();
+        self.levels.insert(
+            id,
+            Level {
+                grid,
+                // New levels are as dark as the current one
+                lighting: Lighting::new(self.lighting.map().ambient()),
+                ..Default::default()
+            },
+        );
+        id
+    }
+
+    // Used to make up the levels behind stairs that don't lead anywhere yet
+    pub fn set_level_generator(&mut self, generator: impl LevelGenerator + 'static) {
+        self.generator = Some(Box::new(generator));
+    }
+
+    // Where the stairs on the current level lead, None if they haven't been taken yet
+    pub fn stairs(&self, at: impl AsPosition) -> Option<StairsLink> {
+        self.stairs.get(&at.into()).copied()
+    }
+
+    // Puts stairs on both ends and connects them
+    pub fn link_stairs(&mut self, a: StairsLink, b: StairsLink) -> Result<(), ActionError> {
+        self.connect_stairs(a, b)?;
+        // Whoever is looking sees the stairs appear
+        self.update_knowledge();
+        Ok(())
+    }
+
+    pub fn is_alive(&self, actor: ActorId) -> bool {
+        self.actors.contains(actor)
+    }
+
+    pub fn actor(&self, actor: ActorId) -> Option<&ActorData> {
+        self.actors.get(actor)
+    }
+
+    pub fn actor_mut(&mut self, actor: ActorId) -> Option<&mut ActorData> {
+        self.actors.get_mut(actor)
+    }
+
+    pub fn knowledge(&self, observer: Observer) -> Option<&Knowledge> {
+        self.knowledge.get(&observer)
+    }
+
+    pub fn light_map(&self) -> &LightMap {
+        self.lighting.map()
+    }
+
+    // Light that reaches every tile, full brightness unless changed
+    pub fn set_ambient_light(&mut self, ambient: [f32; 3]) {
+        self.lighting.set_ambient(&self.grid, ambient);
+        self.update_knowledge();
+    }
+
+    // The tiles the observer sees this turn
+    pub fn visible(&self, observer: Observer) -> Option<&HashSet<Position>> {
+        self.knowledge(observer).map(Knowledge::visible)
+    }
+
+    // Starts keeping track of what the observer sees and remembers
+    pub fn track_knowledge(&mut self, observer: Observer) {
+        self.knowledge.entry(observer).or_default();
+        self.update_knowledge();
+    }
+
+    // Stops tracking the observer, returning everything it knew
+    pub fn forget_knowledge(&mut self, observer: Observer) -> Option<Knowledge> {
+        self.knowledge.remove(&observer)
+    }
+
+    // Catches every tracked observer up with what it sees right now,
+    // the field of view is only recomputed for the ones that moved or saw the tiles change
+    pub fn update_knowledge(&mut self) {
+        self.refresh_knowledge();
+        self.flush_events();
+    }
+
+    // Same as `update_knowledge`, but leaves the discoveries to be picked up with the rest of the events
+    fn refresh_knowledge(&mut self) {
+        // Tiles whose material, occupier or feature changed since the last flush
+        let touched = self
+            .grid
+            .events
+            .borrow()
+            .iter()
+            .flat_map(|event| match *event {
+                Event::ActorMoved { from, to, .. } => vec![from, to],
+                Event::FeatureChanged { position, .. } | Event::TileChanged { position, .. } => {
+                    vec![position]
+                }
+                Event::ActorSpawned { at, .. }
+                | Event::ActorDespawned { at, .. }
+                | Event::ActorDied { at, .. } => vec![at],
+                _ => vec![],
+            })
+            .collect::<Vec<_>>();
+
+        // Lights carried by actors as well as the ones placed on the grid
+        let sources = self
+            .grid
+            .lights()
+            .map(|(x, y)| (x, *y))
+            .chain(
+                self.components
+                    .query::<LightSource>()
+                    .filter_map(|(id, light)| self.actors.get(id).map(|x| (x, *light)))
+                    .filter(|(data, _)| data.level() == self.level)
+                    .map(|(data, light)| (data.position(), light)),
+            )
+            .collect();
+        self.lighting.update(&self.grid, sources);
+
+        let observers = self.knowledge.keys().copied().collect::<Vec<_>>();
+        for observer in observers {
+            let viewpoints = self.viewpoints(observer);
+            let knowledge = self
+                .knowledge
+                .get_mut(&observer)
+                .expect("Observer was just listed");
+
+            if !knowledge.is_outdated(&viewpoints, &self.grid, &self.lighting) {
+                knowledge.glimpse(&self.grid, &self.actors, touched.iter().copied());
+                continue;
+            }
+
+            for position in knowledge.observe(&self.grid, &self.actors, &self.lighting, viewpoints)
+            {
+                self.grid
+                    .record(Event::TileDiscovered { observer, position });
+            }
+        }
+    }
+
+    // Every place the observer looks from, empty if none of the actors it includes are around
+    fn viewpoints(&self, observer: Observer) -> Vec<Viewpoint> {
+        self.actors
+            .on_level(self.level)
+            .filter(|(id, _)| observer.includes(*id, &self.actors))
+            .map(|(_, data)| Viewpoint {
+                origin: data.position(),
+                radius: data.template().sight_radius(),
+            })
+            .collect()
+    }
+
+    // Attaches the component to a living actor, replacing the one of the same type
+    pub fn insert_component<T: Component>(
+        &mut self,
+        actor: ActorId,
+        component: T,
+    ) -> Result<Option<T>, ActionError> {
+        if !self.is_alive(actor) {
+            return Err(ActionError::InvalidActor);
+        }
+
+        Ok(self.components.insert(actor, component))
+    }
+
+    pub fn component<T: Component>(&self, actor: ActorId) -> Option<&T> {
+        self.components.get(actor)
+    }
+
+    pub fn component_mut<T: Component>(&mut self, actor: ActorId) -> Option<&mut T> {
+        self.components.get_mut(actor)
+    }
+
+    pub fn remove_component<T: Component>(&mut self, actor: ActorId) -> Option<T> {
+        self.components.remove(actor)
+    }
+
+    // Every actor that has a component of the type, along with it
+    pub fn query<T: Component>(&self) -> impl Iterator<Item = (ActorId, &T)> {
+        self.components.query()
+    }
+
+    pub fn query_mut<T: Component>(&mut self) -> impl Iterator<Item = (ActorId, &mut T)> {
+        self.components.query_mut()
+    }
+
+    pub fn spawn_actor(&mut self, position: impl AsPosition, actor: Actor) -> Option<ActorId> {
+        let position = position.into();
+        let id = self.actors.insert(actor, self.level, position);
+
+        if self.grid.put_actor(position, id).is_err() {
+            self.actors.remove(id);
+            return None;
+        }
+
+        self.scheduler.schedule(id);
+        self.update_knowledge();
+        Some(id)
+    }
+
+    // Spawns the actor whose turns are decided outside of the world
+    pub fn spawn_player(&mut self, position: impl AsPosition, actor: Actor) -> Option<ActorId> {
+        let id = self.spawn_actor(position, actor)?;
+        self.player = Some(id);
+        Some(id)
+    }
+
+    // Removes the actor from the world, its id is never valid again
+    pub fn despawn(&mut self, actor: ActorId) -> Result<Actor, ActionError> {
+        let (level, position) = self
+            .actors
+            .get(actor)
+            .map(|x| (x.level(), x.position()))
+            .ok_or(ActionError::InvalidActor)?;
+
+        let is_elsewhere = level != self.level;
+        let (grid, _) = self
+            .level_parts(level)
+            .expect("Actors are always on an existing level");
+        grid.remove_actor(position)?;
+        // Nobody on other levels is there to see it go
+        if is_elsewhere {
+            grid.take_events();
         }
+
+        self.scheduler.unschedule(actor);
+        self.components.remove_all(actor);
+
+        let data = self
+            .actors
+            .remove(actor)
+            .expect("Actor was checked to be alive");
+        self.update_knowledge();
+        Ok(data.actor)
     }
 
-    fn move_actor(&mut self, actor: ActorReference, destination: Position) -> bool {
-        let position = actor.try_as_valid().map(|x| x.1.cached_position);
-        let from = match position {
-            Some(pos) => pos,
-            None => return false,
+    pub fn submit_action(&mut self, action: Action) -> ActionResult {
+        // Whatever is pending doesn't belong to this action
+        self.flush_events();
+
+        // Other levels are frozen until the player gets there
+        let level = self
+            .actors
+            .get(action.actor())
+            .map(ActorData::level)
+            .ok_or(ActionError::InvalidActor)?;
+        if level != self.level {
+            return Err(ActionError::ElsewhereActor(level));
+        }
+        if self.scheduler.ready(&self.actors) != Some(action.actor()) {
+            return Err(ActionError::NotReady);
+        }
+
+        match action {
+            Action::MoveActor { actor, to } => self.move_actor(actor, to)?,
+            Action::Attack { actor, target } => self.attack(actor, target)?,
+            Action::Wait { .. } => {}
+            Action::Interact { actor, at } => self.interact(actor, at)?,
         };
 
-        if self
+        self.spend_energy(action.actor(), action.cost());
+
+        self.refresh_knowledge();
+
+        let events = self.grid.take_events();
+        let outcome = ActionOutcome::from_events(events.iter().cloned());
+        self.events.publish(events);
+        Ok(outcome)
+    }
+
+    // Lets every other actor take their turns until the player has to decide.
+    // Returns immediately if there is no player in the world, or if the player can never act.
+    pub fn advance(&mut self, controller: &mut impl Controller) {
+        while let Some(player) = self.player {
+            if !self.scheduler.is_scheduled(player) {
+                return;
+            }
+
+            // A player without speed would leave everyone else acting forever
+            let is_stuck = self.actors.get(player).map_or(true, |x| {
+                x.energy < TURN_ENERGY && x.template().speed() <= 0
+            });
+            if is_stuck {
+                return;
+            }
+
+            let actor = match self.scheduler.next_ready(&mut self.actors) {
+                Some(actor) => actor,
+                None => return,
+            };
+
+            if actor == player {
+                return;
+            }
+
+            let action = controller.decide(self, actor);
+            if self.submit_action(action).is_err() {
+                // Failing to act still costs the turn, otherwise the actor would retry forever
+                self.spend_energy(actor, Action::wait(actor).cost());
+            }
+        }
+    }
+
+    fn spend_energy(&mut self, actor: ActorId, cost: i32) {
+        if let Some(data) = self.actors.get_mut(actor) {
+            data.energy -= cost;
+        }
+    }
+
+    fn move_actor(&mut self, actor: ActorId, destination: Position) -> Result<(), ActionError> {
+        let mover = self.actors.get(actor).ok_or(ActionError::InvalidActor)?;
+        let from = mover.position();
+
+        let tile = self
             .grid
             .get_tile(destination)
-            .map(Tile::is_walkable)
-            .map(std::ops::Not::not)
-            .unwrap_or(false)
+            .ok_or(ActionError::NoTile(destination))?;
+
+        // Bumping into a hostile actor attacks it instead
+        if let Some(occupier) = tile.occupier {
+            let is_hostile = self
+                .actors
+                .get(occupier)
+                .map_or(false, |x| mover.template().is_hostile_to(x.template()));
+
+            return if is_hostile {
+                self.attack(actor, occupier)
+            } else {
+                Err(ActionError::Occupied(destination))
+            };
+        }
+
+        // Bumping into a closed door opens it
+        if let Some(feature) = tile.feature {
+            if feature.kind == FeatureKind::Door && feature.state == FeatureState::Closed {
+                return self.interact(actor, destination);
+            }
+        }
+
+        if !tile.is_walkable() {
+            return Err(ActionError::NotWalkable(destination));
+        }
+
+        self.grid.move_actor(from, destination)?;
+        self.actors.relocate(actor, destination);
+
+        Ok(())
+    }
+
+    fn interact(&mut self, actor: ActorId, at: Position) -> Result<(), ActionError> {
+        let position = self
+            .actors
+            .get(actor)
+            .map(ActorData::position)
+            .ok_or(ActionError::InvalidActor)?;
+
+        let tile = self.grid.get_tile(at).ok_or(ActionError::NoTile(at))?;
+        let reach = at - position;
+        if reach.x.abs() > 1 || reach.y.abs() > 1 {
+            return Err(ActionError::OutOfReach(at));
+        }
+
+        let feature = tile.feature.ok_or(ActionError::NoFeature(at))?;
+        if feature.state == FeatureState::Locked {
+            return Err(ActionError::Locked(at));
+        }
+
+        if feature.kind == FeatureKind::Stairs {
+            // Stairs are taken by whoever stands on them
+            if position != at {
+                return Err(ActionError::OutOfReach(at));
+            }
+
+            self.grid.record(Event::FeatureInteracted {
+                actor,
+                position: at,
+                feature,
+            });
+            return self.take_stairs(actor, at);
+        }
+
+        let changed = feature.interacted();
+        // Nothing can be closed on top of someone
+        if changed.map_or(false, |x| x.blocks_movement()) && tile.is_occupied() {
+            return Err(ActionError::Occupied(at));
+        }
+
+        self.grid.record(Event::FeatureInteracted {
+            actor,
+            position: at,
+            feature,
+        });
+
+        if let Some(changed) = changed {
+            self.grid.set_feature(at, Some(changed))?;
+        }
+
+        Ok(())
+    }
+
+    fn attack(&mut self, attacker: ActorId, target: ActorId) -> Result<(), ActionError> {
+        let attacker_data = self.actors.get(attacker).ok_or(ActionError::InvalidActor)?;
+        let target_data = self.actors.get(target).ok_or(ActionError::InvalidTarget)?;
+
+        let at = target_data.position();
+        let reach = at - attacker_data.position();
+        if target_data.level() != attacker_data.level() || reach.x.abs() > 1 || reach.y.abs() > 1 {
+            return Err(ActionError::OutOfReach(at));
+        }
+
+        if !attacker_data
+            .template()
+            .is_hostile_to(target_data.template())
         {
-            return false;
+            return Err(ActionError::NotHostile);
+        }
+
+        let damage = attacker_data.template().damage();
+        let target_data = self
+            .actors
+            .get_mut(target)
+            .expect("Target was checked to be alive");
+        target_data.health -= damage;
+        let health = target_data.health;
+
+        self.grid.record(Event::ActorAttacked {
+            attacker,
+            target,
+            damage,
+        });
+
+        if health <= 0 {
+            self.grid.record(Event::ActorDied { actor: target, at });
+
+            self.grid.remove_actor(at)?;
+            self.scheduler.unschedule(target);
+            self.components.remove_all(target);
+            self.actors.remove(target);
+        }
+
+        Ok(())
+    }
+
+    fn take_stairs(&mut self, actor: ActorId, at: Position) -> Result<(), ActionError> {
+        let link = match self.stairs.get(&at) {
+            Some(link) => *link,
+            None => self.generate_level(at)?,
+        };
+
+        let arrival = self
+            .level_grid(link.level)
+            .ok_or(ActionError::NoLevel(link.level))?
+            .get_tile(link.position)
+            .ok_or(ActionError::NoTile(link.position))?;
+        if arrival.is_occupied() {
+            return Err(ActionError::Occupied(link.position));
+        }
+
+        let from = self.level;
+        self.grid.remove_actor(at)?;
+        self.actors.transfer(actor, link.level, link.position);
+        self.grid.record(Event::ActorTravelled {
+            actor,
+            from,
+            to: link.level,
+            at: link.position,
+        });
+
+        if Some(actor) == self.player {
+            self.switch_level(link.level);
+            self.grid.put_actor(link.position, actor)?;
+        } else {
+            self.scheduler.unschedule(actor);
+            let (grid, _) = self
+                .level_parts(link.level)
+                .expect("Level was checked to exist");
+            grid.put_actor(link.position, actor)?;
+            grid.take_events();
+        }
+
+        Ok(())
+    }
+
+    // Makes up the level behind the stairs and links the two
+    fn generate_level(&mut self, at: Position) -> Result<StairsLink, ActionError> {
+        let id = LevelId(self.next_level);
+        let generator = self
+            .generator
+            .as_mut()
+            .ok_or(ActionError::NoDestination(at))?;
+        let (grid, arrival) = generator
+            .generate(id)
+            .ok_or(ActionError::NoDestination(at))?;
+        // Checked before the level is added, so stairs never lead to a level that can't be entered
+        if grid.get_tile(arrival).is_none() {
+            return Err(ActionError::NoDestination(at));
+        }
+
+        // Actors elsewhere never move, so anyone spawned on stairs would block them for good
+        let population: Vec<_> = generator
+            .populate(id, &grid)
+            .into_iter()
+            .filter(|(position, _)| {
+                let is_stairs = grid
+                    .get_tile(*position)
+                    .and_then(|x| x.feature)
+                    .map_or(false, |x| x.kind == FeatureKind::Stairs);
+                *position != arrival && !is_stairs
+            })
+            .collect();
+
+        let level = self.add_level(grid);
+        let link = StairsLink {
+            level,
+            position: arrival,
+        };
+        self.connect_stairs(
+            StairsLink {
+                level: self.level,
+                position: at,
+            },
+            link,
+        )?;
+
+        // Levels other than the current one are always kept aside
+        let grid = &mut self
+            .levels
+            .get_mut(&level)
+            .expect("Level was just added")
+            .grid;
+        for (position, actor) in population {
+            let id = self.actors.insert(actor, level, position);
+            if grid.put_actor(position, id).is_err() {
+                self.actors.remove(id);
+            }
+        }
+        grid.take_events();
+
+        Ok(link)
+    }
+
+    fn connect_stairs(&mut self, a: StairsLink, b: StairsLink) -> Result<(), ActionError> {
+        let current = self.level;
+        for (from, to) in [(a, b), (b, a)] {
+            let (grid, stairs) = self
+                .level_parts(from.level)
+                .ok_or(ActionError::NoLevel(from.level))?;
+
+            let tile = grid
+                .get_tile(from.position)
+                .ok_or(ActionError::NoTile(from.position))?;
+            if tile.feature.map(|x| x.kind) != Some(FeatureKind::Stairs) {
+                grid.set_feature(from.position, Some(Feature::stairs()))?;
+            }
+
+            // Nobody on other levels is there to see the stairs appear
+            if from.level != current {
+                grid.take_events();
+            }
+            stairs.insert(from.position, to);
+        }
+
+        Ok(())
+    }
+
+    // Freezes the current level and thaws the other one
+    fn switch_level(&mut self, to: LevelId) {
+        let Some(mut next) = self.levels.remove(&to) else {
+            return;
+        };
+
+        // Observers keep being tracked wherever they go
+        for observer in self.knowledge.keys() {
+            next.knowledge.entry(*observer).or_default();
         }
 
-        self.grid.move_actor(from, destination);
-        true
+        let pending = self.grid.take_events();
+        let previous = Level {
+            grid: std::mem::replace(&mut self.grid, next.grid),
+            knowledge: std::mem::replace(&mut self.knowledge, next.knowledge),
+            lighting: std::mem::replace(&mut self.lighting, next.lighting),
+            stairs: std::mem::replace(&mut self.stairs, next.stairs),
+        };
+        let from = std::mem::replace(&mut self.level, to);
+        self.levels.insert(from, previous);
+
+        self.grid.events.replace(pending);
+        self.grid.record(Event::LevelChanged { from, to });
+
+        // Only the actors on the current level take turns
+        self.scheduler = Scheduler::default();
+        for (id, _) in self.actors.on_level(to) {
+            self.scheduler.schedule(id);
+        }
+    }
+
+    // Grid and stairs of any level, wherever they are kept
+    fn level_parts(
+        &mut self,
+        level: LevelId,
+    ) -> Option<(&mut Grid, &mut HashMap<Position, StairsLink>)> {
+        match level == self.level {
+            true => Some((&mut self.grid, &mut self.stairs)),
+            false => self
+                .levels
+                .get_mut(&level)
+                .map(|x| (&mut x.grid, &mut x.stairs)),
+        }
     }
 }